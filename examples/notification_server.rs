@@ -0,0 +1,66 @@
+// Stands in for a notification daemon and prints every notification it gets,
+// to try notifications on a private session bus:
+//
+//   dbus-run-session -- sh -c \
+//     'cargo run --example notification_server & sleep 1; cargo run -- select Speakers'
+use std::collections::HashMap;
+use zbus::blocking::connection;
+use zbus::interface;
+use zbus::zvariant::OwnedValue;
+
+struct Notifications {
+    last_id: u32,
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl Notifications {
+    // The signature is fixed by the notification spec.
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &mut self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        _actions: Vec<String>,
+        _hints: HashMap<String, OwnedValue>,
+        _expire_timeout: i32,
+    ) -> u32 {
+        println!("{} [{}] {}: {}", app_name, app_icon, summary, body);
+        if replaces_id != 0 {
+            return replaces_id;
+        }
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn close_notification(&self, _id: u32) {}
+
+    fn get_capabilities(&self) -> Vec<String> {
+        vec![String::from("body")]
+    }
+
+    fn get_server_information(&self) -> (String, String, String, String) {
+        (
+            String::from("notification_server"),
+            String::from("audio-select"),
+            String::from(env!("CARGO_PKG_VERSION")),
+            String::from("1.2"),
+        )
+    }
+}
+
+fn main() -> zbus::Result<()> {
+    let _connection = connection::Builder::session()?
+        .name("org.freedesktop.Notifications")?
+        .serve_at(
+            "/org/freedesktop/Notifications",
+            Notifications { last_id: 0 },
+        )?
+        .build()?;
+    println!("Waiting for notifications");
+    loop {
+        std::thread::park();
+    }
+}
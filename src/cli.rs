@@ -0,0 +1,190 @@
+use crate::data::AppState;
+use crate::notifications;
+use crate::placement::{Anchor, Placement, PlacementMode};
+use crate::watcher;
+
+pub const USAGE: &str = "Usage: audio-select [COMMAND | OPTIONS]
+
+Without a command the device popup is opened.
+
+Options, overriding the placement from the config:
+  --placement <mouse|centered|anchored|fixed|remember>
+                  Where the popup opens
+  --anchor <top-left|top|top-right|left|right|bottom-left|bottom|bottom-right>
+                  Open against an edge or corner of the screen
+  --offset <x,y>  Distance from the edges the popup is anchored to
+  --position <x,y>
+                  Open at fixed screen coordinates
+
+Commands:
+  select [sink:|source:]<device>
+                  Make a device the default and run its select actions
+  scene <name>    Apply the scene with the given name
+  mute-inputs [on|off|toggle]
+                  Mute every input, or restore them, toggling by default
+  status [--follow]
+                  Print the defaults, muted inputs and recording applications,
+                  again on every change with --follow
+  watch           Keep running and apply the switching rules as devices come and go
+  help            Show this message";
+
+pub enum Command {
+    Popup(PopupOptions),
+    Select(String),
+    Scene(String),
+    MuteInputs(Option<bool>),
+    Status { follow: bool },
+    Watch,
+    Help,
+}
+
+impl Command {
+    pub fn parse(args: &[String]) -> Result<Command, String> {
+        let (command, rest) = match args.split_first() {
+            Some(x) => x,
+            None => return Ok(Command::Popup(PopupOptions::default())),
+        };
+        if command.starts_with("--") && command != "--help" {
+            return PopupOptions::parse(args).map(Command::Popup);
+        }
+        match (command.as_str(), rest) {
+            ("select", [device]) => Ok(Command::Select(device.clone())),
+            ("select", _) => Err(String::from("select takes exactly one device")),
+            ("scene", [name]) => Ok(Command::Scene(name.clone())),
+            ("scene", _) => Err(String::from("scene takes exactly one name")),
+            ("mute-inputs", [] | [_]) => match rest.first().map(String::as_str) {
+                None | Some("toggle") => Ok(Command::MuteInputs(None)),
+                Some("on") => Ok(Command::MuteInputs(Some(true))),
+                Some("off") => Ok(Command::MuteInputs(Some(false))),
+                Some(x) => Err(format!("mute-inputs takes on, off or toggle, not {}", x)),
+            },
+            ("status", []) => Ok(Command::Status { follow: false }),
+            ("status", [x]) if x == "--follow" => Ok(Command::Status { follow: true }),
+            ("watch", []) => Ok(Command::Watch),
+            ("help" | "--help" | "-h", []) => Ok(Command::Help),
+            _ => Err(format!("Unknown command {}", args.join(" "))),
+        }
+    }
+}
+
+// Overrides of the configured placement for one launch of the popup.
+#[derive(Default)]
+pub struct PopupOptions {
+    mode: Option<PlacementMode>,
+    anchor: Option<Anchor>,
+    offset: Option<(f64, f64)>,
+    position: Option<(f64, f64)>,
+}
+
+impl PopupOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = PopupOptions::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--placement" => {
+                    options.mode = Some(
+                        PlacementMode::from_name(value)
+                            .ok_or_else(|| format!("Unknown placement {}", value))?,
+                    )
+                }
+                "--anchor" => {
+                    options.anchor = Some(
+                        Anchor::from_name(value)
+                            .ok_or_else(|| format!("Unknown anchor {}", value))?,
+                    )
+                }
+                "--offset" => options.offset = Some(coordinates(value)?),
+                "--position" => options.position = Some(coordinates(value)?),
+                _ => return Err(format!("Unknown option {}", flag)),
+            }
+        }
+        Ok(options)
+    }
+
+    // An anchor or a position picks its mode unless --placement is given too.
+    pub fn apply(&self, mut placement: Placement) -> Placement {
+        if let Some(anchor) = self.anchor {
+            placement.anchor = anchor;
+            placement.mode = PlacementMode::Anchored;
+        }
+        if let Some(position) = self.position {
+            placement.position = position;
+            placement.mode = PlacementMode::Fixed;
+        }
+        if let Some(offset) = self.offset {
+            placement.offset = offset;
+        }
+        if let Some(mode) = self.mode {
+            placement.mode = mode;
+        }
+        placement
+    }
+}
+
+fn coordinates(value: &str) -> Result<(f64, f64), String> {
+    value
+        .split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .ok_or_else(|| format!("Expected x,y instead of {}", value))
+}
+
+// Runs a command without opening a window and returns the exit code.
+pub fn run(command: Command) -> i32 {
+    let mut state = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        _ => AppState::new(),
+    };
+    if !state.ready {
+        eprintln!("{}", state.not_ready_string);
+        return 1;
+    }
+    let failures = match command {
+        Command::Select(device) => match state.resolve_device(&device) {
+            Some((device_type, name)) => state.select_device(&device_type, &name),
+            None => vec![format!("{} is not connected", device)],
+        },
+        Command::Scene(name) => state.apply_scene(&name),
+        Command::MuteInputs(mute) => {
+            let mute = mute.unwrap_or(!state.inputs_muted);
+            state.mute_inputs(mute)
+        }
+        Command::Status { follow } => {
+            let mut line = state.status_line();
+            println!("{}", line);
+            if !follow {
+                return 0;
+            }
+            // Only reads the state, switching is left to the popup or watch.
+            watcher::run(|_| {
+                state.refresh();
+                if state.status_line() != line {
+                    line = state.status_line();
+                    println!("{}", line);
+                }
+            });
+            vec![String::from("Lost the connection to PulseAudio")]
+        }
+        Command::Watch => {
+            watcher::run(|events| state.handle_events(&events));
+            vec![String::from("Lost the connection to PulseAudio")]
+        }
+        Command::Popup(_) | Command::Help => Vec::new(),
+    };
+    let failures = [failures, state.wait_for_commands()].concat();
+    notifications::flush();
+    for failure in &failures {
+        eprintln!("{}", failure);
+    }
+    if failures.is_empty() {
+        0
+    } else {
+        1
+    }
+}
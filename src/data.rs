@@ -1,11 +1,27 @@
 #![allow(clippy::arc_with_non_send_sync)]
-use crate::pulsewrapper::{PulseWrapper, PulseWrapperError};
-use druid::{Data, ExtEventSink, Lens};
+use crate::icons::device_icon;
+use crate::keys::KeyBindings;
+use crate::labels::{automatic_label, LabelSubstitution};
+use crate::matching::{
+    bus_of, fuzzy_score, glob_match, is_monitor, DeviceIdentity, DeviceRule, Pattern,
+    APPLICATION_NAME_PROPERTY, FORM_FACTOR_PROPERTY, ICON_NAME_PROPERTY, SEARCH_PROPERTIES,
+};
+use crate::notifications;
+use crate::placement::Placement;
+use crate::pulsewrapper::{
+    Card, Change, Choice, DeviceInfo, Facility, PulseWrapper, PulseWrapperError, ServerEvent,
+    StreamInfo, MAX_VOLUME_PERCENT,
+};
+use crate::volumes::{SavedVolumes, VolumeState};
+use druid::{Data, ExtEventSink, Lens, Size};
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::BTreeMap;
-use std::process::Command;
+use std::io;
+use std::process::{Child, Command, ExitStatus};
 use std::sync::Arc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::vec::Vec;
 
 #[derive(Clone, PartialEq, Data)]
@@ -14,6 +30,42 @@ pub enum AudioDeviceType {
     Sink,
 }
 
+// Why the default changed, each can be set to notify or not.
+#[derive(Clone, Copy, PartialEq)]
+enum DefaultChange {
+    Selected,
+    Automatic,
+    FallBack,
+    Lock,
+}
+
+impl AudioDeviceType {
+    pub fn other(&self) -> Self {
+        match self {
+            AudioDeviceType::Source => AudioDeviceType::Sink,
+            AudioDeviceType::Sink => AudioDeviceType::Source,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Debug, PartialEq, Data)]
+#[serde(rename_all = "snake_case")]
+pub enum SortMode {
+    Manual,
+    Alphabetical,
+    MostUsed,
+    RecentlyConnected,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Debug, PartialEq, Data)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Nothing,
+    Card,
+    Bus,
+    Custom,
+}
+
 #[derive(Clone, Data, Lens)]
 pub struct AppState {
     pub ready: bool,
@@ -24,24 +76,174 @@ pub struct AppState {
     pub default_sink: String,
     pulsewrapper: Arc<RefCell<PulseWrapper>>,
     pub use_dark_theme: bool,
+    pub font: String,
+    pub text_size: f64,
+    pub hide_monitors: bool,
+    pub sort_mode: SortMode,
+    pub group_by: GroupBy,
+    collapsed_groups: Arc<Vec<String>>,
     pub close_on_leave: bool,
+    pub close_on_focus_loss: bool,
+    pub close_delay: u64, // milliseconds
+    pub pinned: bool,     // shown as a small mixer that stays open on top
+    pub placement: Placement,
+    pub move_streams_on_switch: bool,
+    pub volume_step: u32,
+    // Lists and name rules from the config, edited one entry per line
+    pub preferred_sources: String,
+    pub preferred_sinks: String,
+    pub fallback_sources: String,
+    pub fallback_sinks: String,
+    pub hidden_names: String,
+    pub shown_names: String,
+    pub unsaved: bool, // settings changed since they were last saved
+    pub scenes: Arc<Vec<String>>,
+    pub inputs_muted: bool,          // every input muted by the privacy toggle
+    pub recording: Arc<Vec<String>>, // applications with recording streams
+    pub locked_source: Option<String>,
+    pub locked_sink: Option<String>,
+    lock_paused_until: Option<u64>, // seconds since the epoch
+    active_app_rule: Option<usize>,
+    // Source and sink from before an app rule changed them, restored once no
+    // rule applies. Directions no rule changed are left as they are.
+    app_rule_defaults: (Option<String>, Option<String>),
+    pub notify_on_select: bool,
+    pub notify_on_automatic: bool,
+    pub notify_on_fallback: bool,
+    pub notify_on_lock: bool,
+    // Device highlighted for the keyboard
+    focus: Option<(AudioDeviceType, String)>,
+    // Device being renamed in the popup and its label from before
+    renaming: Option<(AudioDeviceType, String, String)>,
+    pub search: String, // filters the popup lists
+    pub status: String, // errors from the last action, shown in the popup
+    // Set by the popup, which is told about select commands that fail later
+    #[data(ignore)]
+    event_sink: Option<ExtEventSink>,
+    // Select commands started without a popup, waited for before exiting
+    #[data(ignore)]
+    commands: Arc<RefCell<Vec<(String, Child)>>>,
+    config: Arc<AppConfig>,
 }
 
 #[derive(Clone, Data, Lens)]
 pub struct AudioDeviceState {
-    pub label: String,
+    pub label: String,         // empty unless the user labelled the device
+    pub default_label: String, // built from the label templates
     pub name: String,
     pub device_type: AudioDeviceType,
-    pub connected: bool, // true if device is recognized by pulseaudio
-    pub hidden: bool,    // true if user decides to hide device
+    pub connected: bool,   // true if device is recognized by pulseaudio
+    pub hidden: bool,      // true if user decides to hide device
+    pub rule_hidden: bool, // true if a hide rule in the config matches
+    pub favourite: bool,   // pinned to the top of the popup
+    pub use_count: u32,
+    pub index: u32,           // pulseaudio index, grows as devices are connected
+    pub card: String,         // description of the card, empty for virtual devices
+    pub pair: Option<String>, // paired device declared in the config
+    pub partner: String,      // declared or inferred pair, empty if none
+    pub unpaired: bool,       // true if the pair shouldn't switch together
+    pub volume: u32,          // percent
+    pub muted: bool,
+    pub on_select: SelectActions,
+    pub headphones: bool, // volume is capped when selected
+    pub icon: String,     // as configured, the shown icon is icon_path
+    pub icon_path: String,
+    pub running: bool,
+    pub suspended: bool,
+    pub apps: usize,    // applications playing or recording through it
+    pub focused: bool,  // highlighted for the keyboard
+    pub renaming: bool, // label is being edited in the popup
+    pub identity: DeviceIdentity,
+    pub properties: Arc<BTreeMap<String, String>>,
     pub pulsewrapper: Arc<RefCell<PulseWrapper>>,
 }
 
+// A section of the popup, as shown when grouping is enabled.
+#[derive(Clone, Data, Lens)]
+pub struct DeviceGroup {
+    pub key: String,
+    pub title: String,
+    pub collapsed: bool,
+    pub devices: Arc<Vec<AudioDeviceState>>,
+}
+
+#[derive(Default)]
+pub struct DeviceChoices {
+    pub ports: Vec<Choice>,
+    pub active_port: Option<String>,
+    pub profiles: Vec<Choice>,
+    pub active_profile: Option<String>,
+}
+
 #[derive(Serialize, Clone, Deserialize, Debug)]
+#[serde(default)]
 pub struct AppConfig {
     pub use_dark_theme: bool,
+    pub font: String, // family, the system font when empty
+    pub text_size: f64,
+    pub hide_monitors: bool,
+    pub sort_mode: SortMode,
+    pub group_by: GroupBy,
+    pub collapsed_groups: Vec<String>,
+    pub label_templates: Vec<String>,
+    // Devices by name, label or glob, most preferred first. One of them becomes
+    // the default when it connects, if it ranks above the current default.
+    pub preferred_sources: Vec<String>,
+    pub preferred_sinks: Vec<String>,
+    pub move_streams_on_switch: bool,
+    // Tried in order when the default disconnects, hidden devices are skipped
+    pub fallback_sources: Vec<String>,
+    pub fallback_sinks: Vec<String>,
+    // Defaults changed by other applications are reverted to these
+    pub locked_source: Option<String>,
+    pub locked_sink: Option<String>,
+    pub lock_override_minutes: u64,
+    // Seconds since the epoch until which other applications may change the
+    // defaults, kept here so `watch` sees a pause started in the popup
+    #[serde(default)]
+    pub lock_paused_until: Option<u64>,
+    // Reapplies the volume and mute state a device last had when selected
+    pub restore_volume_on_select: bool,
+    // Percent, for devices marked as headphones or reporting that form factor
+    pub headphone_volume_cap: Option<u32>,
+    // Which changes of the default show a desktop notification
+    pub notify_on_select: bool,
+    pub notify_on_automatic: bool,
+    pub notify_on_fallback: bool,
+    pub notify_on_lock: bool,
+    pub volume_step: u32, // percent, for the volume keys
+    // When the popup closes by itself. The delay after the pointer leaves is in
+    // milliseconds, so overshooting the edge doesn't close it.
+    pub close_on_leave: bool,
+    pub close_on_focus_loss: bool,
+    pub close_delay: u64,
+    pub pinned: bool,
+    // Bounds for the popup, which is sized to fit the devices
+    pub min_window_size: (f64, f64),
+    pub max_window_size: (f64, f64),
+    // TOML can't have plain values after a table, and an empty list is one, so
+    // empty lists are left out and tables come last.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub sources: Vec<AudioDeviceConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub sinks: Vec<AudioDeviceConfig>,
+    // Show rules win over hide rules and the monitor toggle
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hide_rules: Vec<DeviceRule>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub show_rules: Vec<DeviceRule>,
+    // Applied in order to automatic labels, e.g. to strip "Monitor of "
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub label_substitutions: Vec<LabelSubstitution>,
+    // Used when grouping by custom groups, devices matching none go in "Other"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<DeviceGroupConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scenes: Vec<Scene>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub app_rules: Vec<AppRule>,
+    pub placement: Placement,
+    pub keys: KeyBindings,
 }
 
 #[derive(Serialize, Clone, Deserialize, Debug)]
@@ -49,93 +251,1969 @@ pub struct AudioDeviceConfig {
     pub name: String,
     pub label: String,
     pub hidden: bool,
+    #[serde(default)]
+    pub favourite: bool,
+    #[serde(default)]
+    pub use_count: u32,
+    pub pair: Option<String>,
+    #[serde(default)]
+    pub unpaired: bool,
+    #[serde(default)]
+    pub headphones: bool,
+    // Icon name or path to an image, automatic when empty
+    #[serde(default)]
+    pub icon: String,
+    // Older configs had the select volume here, it moves to on_select on save
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_on_select: Option<u32>,
+    #[serde(default)]
+    pub identity: DeviceIdentity,
+    #[serde(default)]
+    pub on_select: SelectActions,
+}
+
+// Applied after the device is picked in the popup or through the CLI. The
+// volume, in percent, is set just before the device becomes the default, also
+// when it does any other way, and the rest follow in this order.
+#[derive(Serialize, Clone, Deserialize, Debug, Default, PartialEq, Data)]
+#[serde(default)]
+pub struct SelectActions {
+    pub profile: Option<String>,
+    pub port: Option<String>,
+    pub volume: Option<u32>,
+    pub unmute: bool,
+    pub command: Option<String>, // run with sh -c
+}
+
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct DeviceGroupConfig {
+    pub name: String,
+    pub rules: Vec<DeviceRule>,
+}
+
+// Devices are referred to by pulseaudio name, label or a glob over names,
+// cards by name or description. Volumes are in percent.
+#[derive(Serialize, Clone, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Scene {
+    pub name: String,
+    pub sink: Option<String>,
+    pub source: Option<String>,
+    pub profiles: BTreeMap<String, String>,
+    pub ports: BTreeMap<String, String>,
+    pub volumes: BTreeMap<String, u32>,
+    pub mute: BTreeMap<String, bool>,
+}
+
+// Routes an application while it has a stream open. `application` is matched
+// against application.name, `properties` against any other stream property.
+// Devices are referred to like in scenes.
+#[derive(Serialize, Clone, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct AppRule {
+    pub application: Option<Pattern>,
+    pub sink: Option<String>,
+    pub source: Option<String>,
+    pub properties: BTreeMap<String, Pattern>,
+}
+
+impl AppRule {
+    fn matches(&self, properties: &BTreeMap<String, String>) -> bool {
+        let application = properties.get(APPLICATION_NAME_PROPERTY);
+        (self.application.is_some() || !self.properties.is_empty())
+            && self
+                .application
+                .as_ref()
+                .is_none_or(|x| application.is_some_and(|name| x.matches(name)))
+            && self.properties.iter().all(|(key, pattern)| {
+                properties
+                    .get(key)
+                    .is_some_and(|value| pattern.matches(value))
+            })
+    }
 }
 
 impl AppState {
     pub fn new() -> Self {
-        let mut config: AppConfig = confy::load("audio-select", None).unwrap();
+        let config: Arc<AppConfig> = Arc::new(confy::load("audio-select", None).unwrap());
 
         let mut pulsewrapper = PulseWrapper::new();
+        let connected = pulsewrapper.connect();
 
-        if let PulseWrapperError::Err = pulsewrapper.connect() {
-            return AppState {
-                ready: false,
-                not_ready_string: String::from("Failed to connect to PulseAudio"),
-                sources: Arc::new(Vec::new()),
-                sinks: Arc::new(Vec::new()),
-                default_source: String::new(),
-                default_sink: String::new(),
-                pulsewrapper: Arc::new(RefCell::new(pulsewrapper)),
-                use_dark_theme: config.use_dark_theme,
-                close_on_leave: true,
-            };
+        let mut state = AppState {
+            ready: false,
+            not_ready_string: String::from("Failed to connect to PulseAudio"),
+            sources: Arc::new(Vec::new()),
+            sinks: Arc::new(Vec::new()),
+            default_source: String::new(),
+            default_sink: String::new(),
+            pulsewrapper: Arc::new(RefCell::new(pulsewrapper)),
+            use_dark_theme: config.use_dark_theme,
+            font: config.font.clone(),
+            text_size: config.text_size,
+            hide_monitors: config.hide_monitors,
+            sort_mode: config.sort_mode,
+            group_by: config.group_by,
+            collapsed_groups: Arc::new(config.collapsed_groups.clone()),
+            close_on_leave: config.close_on_leave,
+            close_on_focus_loss: config.close_on_focus_loss,
+            close_delay: config.close_delay,
+            pinned: config.pinned,
+            placement: config.placement.clone(),
+            move_streams_on_switch: config.move_streams_on_switch,
+            volume_step: config.volume_step,
+            preferred_sources: config.preferred_sources.join("\n"),
+            preferred_sinks: config.preferred_sinks.join("\n"),
+            fallback_sources: config.fallback_sources.join("\n"),
+            fallback_sinks: config.fallback_sinks.join("\n"),
+            hidden_names: name_patterns(&config.hide_rules),
+            shown_names: name_patterns(&config.show_rules),
+            unsaved: false,
+            scenes: Arc::new(config.scenes.iter().map(|x| x.name.clone()).collect()),
+            inputs_muted: false,
+            recording: Arc::new(Vec::new()),
+            locked_source: config.locked_source.clone(),
+            locked_sink: config.locked_sink.clone(),
+            lock_paused_until: config.lock_paused_until,
+            notify_on_select: config.notify_on_select,
+            notify_on_automatic: config.notify_on_automatic,
+            notify_on_fallback: config.notify_on_fallback,
+            notify_on_lock: config.notify_on_lock,
+            focus: None,
+            renaming: None,
+            search: String::new(),
+            active_app_rule: None,
+            app_rule_defaults: (None, None),
+            status: String::new(),
+            event_sink: None,
+            commands: Arc::new(RefCell::new(Vec::new())),
+            config: config.clone(),
+        };
+        if let PulseWrapperError::Ok = connected {
+            state.ready = true;
+            state.not_ready_string = String::new();
+            state.load(&config);
         }
+        state
+    }
+
+    // Reads devices and defaults from pulseaudio again, keeping any unsaved
+    // edits to labels and flags.
+    pub fn refresh(&mut self) {
+        let config = self.current_config();
+        self.load(&config);
+    }
 
+    fn load(&mut self, config: &AppConfig) {
+        // Defaults that were already in use before this refresh
+        let kept_defaults = [AudioDeviceType::Source, AudioDeviceType::Sink].map(|x| {
+            self.device(&x, self.default_of(&x))
+                .filter(|x| x.connected)
+                .map(|x| x.name.clone())
+        });
+        let pulsewrapper = self.pulsewrapper.borrow();
         let (default_source, default_sink) = pulsewrapper.get_defaults();
-        let pa_sources = pulsewrapper.get_sources();
-        let pa_sinks = pulsewrapper.get_sinks();
-        let mut pa_source_map = pa_sources.into_iter().collect::<BTreeMap<_, _>>();
-        let mut pa_sink_map = pa_sinks.into_iter().collect::<BTreeMap<_, _>>();
-
-        let pulsewrapper = Arc::new(RefCell::new(pulsewrapper));
-
-        let mut sources = Vec::new();
-        let mut sinks = Vec::new();
-
-        for source in &mut config.sources {
-            let connected = pa_source_map.remove(&source.name).is_some();
-            sources.push(AudioDeviceState {
-                name: source.name.clone(),
-                label: source.label.clone(),
-                device_type: AudioDeviceType::Source,
-                connected,
-                hidden: source.hidden,
-                pulsewrapper: pulsewrapper.clone(),
+        let cards = pulsewrapper.get_cards();
+        let sources = load_devices(
+            config,
+            pulsewrapper.get_sources(),
+            AudioDeviceType::Source,
+            &cards,
+            &self.pulsewrapper,
+        );
+        let sinks = load_devices(
+            config,
+            pulsewrapper.get_sinks(),
+            AudioDeviceType::Sink,
+            &cards,
+            &self.pulsewrapper,
+        );
+        drop(pulsewrapper);
+
+        self.default_source = default_source;
+        self.default_sink = default_sink;
+        self.sources = Arc::new(sources);
+        self.sinks = Arc::new(sinks);
+        self.link_pairs();
+        self.apply_rules();
+        self.update_recording();
+        self.update_apps();
+        self.set_focus(self.focus.clone());
+
+        let mut saved = SavedVolumes::load();
+        self.inputs_muted = saved.inputs_before_mute().is_some();
+        // Only defaults are remembered, so a device that reconnects at full
+        // volume doesn't overwrite what it was used at. A device that just
+        // became the default waits for the next refresh, after its volume was
+        // restored.
+        let [kept_source, kept_sink] = kept_defaults;
+        let defaults =
+            self.sources
+                .iter()
+                .filter(|x| Some(&x.name) == kept_source.as_ref() && x.name == self.default_source)
+                .chain(self.sinks.iter().filter(|x| {
+                    Some(&x.name) == kept_sink.as_ref() && x.name == self.default_sink
+                }));
+        saved.remember(defaults.map(|x| {
+            let state = VolumeState {
+                volume: x.volume,
+                mute: x.muted,
+            };
+            (x.name.clone(), state)
+        }));
+    }
+
+    // Recomputes which connected devices are hidden by the configured rules,
+    // independently of the per-device hidden flag.
+    pub fn apply_rules(&mut self) {
+        let config = self.config.clone();
+        let hide_monitors = self.hide_monitors;
+        for devices in [&mut self.sources, &mut self.sinks] {
+            for device in Arc::make_mut(devices) {
+                device.rule_hidden = device.connected
+                    && config.hides(&device.name, &device.properties, hide_monitors);
+            }
+        }
+    }
+
+    // Makes the device the default and runs its select actions. Returns a
+    // message for everything that failed.
+    pub fn select_device(&mut self, device_type: &AudioDeviceType, name: &str) -> Vec<String> {
+        self.select_device_paired(device_type, name, true)
+    }
+
+    // Like select_device, leaving the pair alone when the caller picks the
+    // other direction itself.
+    fn select_device_paired(
+        &mut self,
+        device_type: &AudioDeviceType,
+        name: &str,
+        with_pair: bool,
+    ) -> Vec<String> {
+        let label = self.label_of(device_type, name);
+        if let PulseWrapperError::Err = self.switch_default(device_type, name, with_pair) {
+            return vec![format!("Could not select {}", label)];
+        }
+        let failures = self.run_select_actions(device_type, name);
+        let message = format!("Selected {}", label);
+        self.announce(DefaultChange::Selected, device_type, name, message);
+        // A lock follows whatever the user picks here.
+        for device_type in [AudioDeviceType::Source, AudioDeviceType::Sink] {
+            if self.is_locked(&device_type) {
+                self.lock(&device_type, true);
+            }
+        }
+
+        let use_count = self
+            .devices_mut(device_type)
+            .find(|x| x.name == name)
+            .map(|x| {
+                x.use_count += 1;
+                x.use_count
             });
+        if let Some(use_count) = use_count {
+            self.save_device(device_type, name, |x| x.use_count = use_count);
+        }
+        failures
+    }
+
+    // Sets the profile of the card of a device. Returns the name of the device
+    // afterwards, as the profile recreates it, possibly under another name, or
+    // None if it has no card.
+    fn set_device_profile(
+        &mut self,
+        device_type: &AudioDeviceType,
+        name: &str,
+        profile: &str,
+        failures: &mut Vec<String>,
+    ) -> Option<String> {
+        let device = self.device(device_type, name)?.clone();
+        let label = device.display_label();
+        let card = self.card_of(device_type, name);
+        let card = match card {
+            Some(card) => card,
+            None => {
+                failures.push(format!("{} has no card to set a profile on", label));
+                return None;
+            }
+        };
+        let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+        let result = pulsewrapper.set_card_profile(&card.name, profile);
+        drop(pulsewrapper);
+        if let PulseWrapperError::Err = result {
+            failures.push(format!("Could not set profile {} on {}", profile, label));
+        }
+        self.refresh();
+        let renamed = self
+            .devices_mut(device_type)
+            .find(|x| !x.identity.is_empty() && x.identity == device.identity)
+            .map_or(name.to_string(), |x| x.name.clone());
+        Some(renamed)
+    }
+
+    fn card_of(&self, device_type: &AudioDeviceType, name: &str) -> Option<Card> {
+        let pulsewrapper = self.pulsewrapper.borrow();
+        let devices = match device_type {
+            AudioDeviceType::Source => pulsewrapper.get_sources(),
+            AudioDeviceType::Sink => pulsewrapper.get_sinks(),
+        };
+        let index = devices
+            .iter()
+            .find(|x| x.name == name)
+            .and_then(|x| x.card)?;
+        pulsewrapper
+            .get_cards()
+            .into_iter()
+            .find(|x| x.index == index)
+    }
+
+    fn run_select_actions(&mut self, device_type: &AudioDeviceType, name: &str) -> Vec<String> {
+        let device = match self.device(device_type, name) {
+            Some(device) => device.clone(),
+            None => return Vec::new(),
+        };
+        let actions = &device.on_select;
+        let label = device.display_label();
+        let mut failures = Vec::new();
+        let mut name = name.to_string();
+
+        if let Some(profile) = &actions.profile {
+            if let Some(renamed) =
+                self.set_device_profile(device_type, &name, profile, &mut failures)
+            {
+                name = renamed;
+                // pulseaudio may have picked another default
+                if let PulseWrapperError::Err = self.switch_default(device_type, &name, false) {
+                    failures.push(format!("Could not select {} again", label));
+                }
+            }
+        }
+
+        if let Some(port) = &actions.port {
+            let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+            let result = match device_type {
+                AudioDeviceType::Source => pulsewrapper.set_source_port(&name, port),
+                AudioDeviceType::Sink => pulsewrapper.set_sink_port(&name, port),
+            };
+            drop(pulsewrapper);
+            if let PulseWrapperError::Err = result {
+                failures.push(format!("Could not set port {} on {}", port, label));
+            }
+        }
+
+        if actions.unmute {
+            let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+            let result = match device_type {
+                AudioDeviceType::Source => pulsewrapper.set_source_mute(&name, false),
+                AudioDeviceType::Sink => pulsewrapper.set_sink_mute(&name, false),
+            };
+            drop(pulsewrapper);
+            match result {
+                PulseWrapperError::Ok => {
+                    if let Some(device) = self.devices_mut(device_type).find(|x| x.name == name) {
+                        device.muted = false;
+                    }
+                }
+                PulseWrapperError::Err => failures.push(format!("Could not unmute {}", label)),
+            }
+        }
+
+        if let Some(command) = &actions.command {
+            let child = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("AUDIO_SELECT_DEVICE", &name)
+                .spawn();
+            match child {
+                Ok(child) => self.wait_in_background(command.clone(), child),
+                Err(e) => failures.push(format!("Could not run {}: {}", command, e)),
+            }
+        }
+
+        failures
+    }
+
+    // Reports a select command that fails once it exits, in the popup when
+    // there is one, without holding up the UI meanwhile.
+    fn wait_in_background(&mut self, command: String, mut child: Child) {
+        match self.event_sink.clone() {
+            Some(sink) => {
+                thread::spawn(move || {
+                    if let Some(failure) = command_failure(&command, child.wait()) {
+                        sink.add_idle_callback(move |data: &mut AppState| data.log(failure));
+                    }
+                });
+            }
+            None => self.commands.borrow_mut().push((command, child)),
+        }
+    }
+
+    pub fn set_event_sink(&mut self, sink: ExtEventSink) {
+        self.event_sink = Some(sink);
+    }
+
+    // Waits for the select commands started without a popup and returns a
+    // message for each that failed.
+    pub fn wait_for_commands(&mut self) -> Vec<String> {
+        self.commands
+            .borrow_mut()
+            .drain(..)
+            .filter_map(|(command, mut child)| command_failure(&command, child.wait()))
+            .collect()
+    }
+
+    // Makes the device the default, together with its pair unless told not to
+    // or the pair's direction is locked. Volumes are set first so streams never
+    // play on the new default at its old volume.
+    fn switch_default(
+        &mut self,
+        device_type: &AudioDeviceType,
+        name: &str,
+        with_pair: bool,
+    ) -> PulseWrapperError {
+        self.apply_volume_on_select(device_type, name);
+        let result = self.set_default(device_type, name);
+        if let PulseWrapperError::Err = result {
+            return result;
+        }
+        let other_type = device_type.other();
+        let other_locked = self.is_locked(&other_type) && !self.is_lock_paused();
+        let partner = self
+            .partner_of(device_type, name)
+            .filter(|_| with_pair && !other_locked);
+        if let Some(partner) = partner {
+            self.apply_volume_on_select(&other_type, &partner);
+            if let PulseWrapperError::Err = self.set_default(&other_type, &partner) {
+                eprintln!("Could not select {}, the pair of {}", partner, name)
+            }
+        }
+        result
+    }
+
+    // Sets the configured volume of a device that became the default, or the
+    // one it last had, and keeps headphones under the cap.
+    fn apply_volume_on_select(&mut self, device_type: &AudioDeviceType, name: &str) {
+        let device = match self.device(device_type, name) {
+            Some(device) => device,
+            None => return,
+        };
+        let saved = Some(SavedVolumes::load().get(name))
+            .filter(|_| self.config.restore_volume_on_select)
+            .flatten();
+        let mut volume = device
+            .on_select
+            .volume
+            .or(saved.map(|x| x.volume))
+            .unwrap_or(device.volume);
+        let headphones = device.headphones
+            || matches!(
+                device
+                    .properties
+                    .get(FORM_FACTOR_PROPERTY)
+                    .map(String::as_str),
+                Some("headphone" | "headset")
+            );
+        if let Some(cap) = self.config.headphone_volume_cap.filter(|_| headphones) {
+            volume = volume.min(cap);
+        }
+        let mute = saved.map_or(device.muted, |x| x.mute);
+        let (current_volume, current_mute) = (device.volume, device.muted);
+
+        let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+        let mut result = PulseWrapperError::Ok;
+        if volume != current_volume {
+            result = match device_type {
+                AudioDeviceType::Source => pulsewrapper.set_source_volume(name, volume),
+                AudioDeviceType::Sink => pulsewrapper.set_sink_volume(name, volume),
+            };
+        }
+        if mute != current_mute {
+            if let PulseWrapperError::Err = match device_type {
+                AudioDeviceType::Source => pulsewrapper.set_source_mute(name, mute),
+                AudioDeviceType::Sink => pulsewrapper.set_sink_mute(name, mute),
+            } {
+                result = PulseWrapperError::Err;
+            }
+        }
+        drop(pulsewrapper);
+        if let PulseWrapperError::Err = result {
+            self.log(format!(
+                "Could not restore the volume of {}",
+                self.label_of(device_type, name)
+            ));
+            return;
+        }
+        if let Some(device) = self.devices_mut(device_type).find(|x| x.name == name) {
+            device.volume = volume;
+            device.muted = mute;
+        }
+    }
+
+    fn set_default(&mut self, device_type: &AudioDeviceType, name: &str) -> PulseWrapperError {
+        let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+        let result = match device_type {
+            AudioDeviceType::Source => pulsewrapper.set_default_source(name),
+            AudioDeviceType::Sink => pulsewrapper.set_default_sink(name),
+        };
+        drop(pulsewrapper);
+        if let PulseWrapperError::Ok = result {
+            match device_type {
+                AudioDeviceType::Source => self.default_source = name.to_string(),
+                AudioDeviceType::Sink => self.default_sink = name.to_string(),
+            }
+        }
+        result
+    }
+
+    // The connected device that should be switched along with this one, unless
+    // either side opted out.
+    fn partner_of(&self, device_type: &AudioDeviceType, name: &str) -> Option<String> {
+        let device = self.device(device_type, name)?;
+        let partner = self.device(&device_type.other(), &device.partner)?;
+        if device.unpaired || partner.unpaired || !partner.connected {
+            return None;
+        }
+        Some(partner.name.clone())
+    }
+
+    // Links sinks and sources that belong together. Pairs declared in the config
+    // win, otherwise a pair is inferred when a card or bluetooth device has
+    // exactly one sink and one source, like most headsets.
+    fn link_pairs(&mut self) {
+        let same_hardware = |a: &AudioDeviceState, b: &AudioDeviceState| {
+            a.connected
+                && b.connected
+                && a.pair.is_none()
+                && b.pair.is_none()
+                && !is_monitor(&a.properties)
+                && !is_monitor(&b.properties)
+                && match (&a.identity.bluetooth_address, &b.identity.bluetooth_address) {
+                    (Some(x), Some(y)) => x.eq_ignore_ascii_case(y),
+                    _ => {
+                        !a.card.is_empty()
+                            && a.card == b.card
+                            && a.identity.bus_path == b.identity.bus_path
+                    }
+                }
+        };
+
+        // (source, sink)
+        let mut pairs = Vec::new();
+        for source in self.sources.iter() {
+            if let Some(sink) = &source.pair {
+                pairs.push((source.name.clone(), sink.clone()));
+                continue;
+            }
+            let mut sinks = self.sinks.iter().filter(|x| same_hardware(source, x));
+            if let (Some(sink), None) = (sinks.next(), sinks.next()) {
+                let sources = self.sources.iter().filter(|x| same_hardware(x, sink));
+                if sources.count() == 1 {
+                    pairs.push((source.name.clone(), sink.name.clone()));
+                }
+            }
+        }
+        for sink in self.sinks.iter() {
+            if let Some(source) = &sink.pair {
+                pairs.push((source.clone(), sink.name.clone()));
+            }
+        }
+
+        for source in Arc::make_mut(&mut self.sources) {
+            source.partner = pairs
+                .iter()
+                .find(|x| x.0 == source.name)
+                .map_or_else(String::new, |x| x.1.clone());
+        }
+        for sink in Arc::make_mut(&mut self.sinks) {
+            sink.partner = pairs
+                .iter()
+                .find(|x| x.1 == sink.name)
+                .map_or_else(String::new, |x| x.0.clone());
+        }
+    }
+
+    // Finds a connected output for the reference, or else an input, so a glob
+    // over an output's name doesn't pick its monitor. A `sink:` or `source:`
+    // prefix only looks at that type.
+    pub fn resolve_device(&self, reference: &str) -> Option<(AudioDeviceType, String)> {
+        let (device_types, reference) = match reference.split_once(':') {
+            Some(("sink", rest)) => (vec![AudioDeviceType::Sink], rest),
+            Some(("source", rest)) => (vec![AudioDeviceType::Source], rest),
+            _ => (
+                vec![AudioDeviceType::Sink, AudioDeviceType::Source],
+                reference,
+            ),
+        };
+        device_types
+            .into_iter()
+            .find_map(|x| Some((x.clone(), self.resolve_device_of(&x, reference)?)))
+    }
+
+    // A device whose name changed, e.g. with a bluetooth profile, is still
+    // found by the identity of the entry saved under its old name.
+    fn resolve_device_of(&self, device_type: &AudioDeviceType, reference: &str) -> Option<String> {
+        let (devices, saved) = match device_type {
+            AudioDeviceType::Source => (self.sources.iter(), &self.config.sources),
+            AudioDeviceType::Sink => (self.sinks.iter(), &self.config.sinks),
+        };
+        resolve(devices.clone(), reference)
+            .map(|x| x.1)
+            .or_else(|| {
+                let identity = saved
+                    .iter()
+                    .find(|x| x.name == reference)
+                    .map(|x| &x.identity)
+                    .or_else(|| {
+                        devices
+                            .clone()
+                            .find(|x| x.name == reference)
+                            .map(|x| &x.identity)
+                    })?;
+                devices
+                    .filter(|x| x.connected)
+                    .find(|x| identity.matches_device(&x.name, &x.properties))
+                    .map(|x| x.name.clone())
+            })
+    }
+
+    // Refreshes after devices come and go or defaults change, then applies the
+    // switching policies to what changed.
+    pub fn handle_events(&mut self, events: &[ServerEvent]) {
+        let devices_changed = events.iter().any(|x| match x.facility {
+            Facility::Sink | Facility::Source => x.change != Change::Changed,
+            Facility::Server => true,
+            _ => false,
+        });
+        let mut changed_devices = Vec::new();
+        for event in events.iter().filter(|x| x.change == Change::Changed) {
+            let device = match event.facility {
+                Facility::Sink => (AudioDeviceType::Sink, event.index),
+                Facility::Source => (AudioDeviceType::Source, event.index),
+                _ => continue,
+            };
+            if !changed_devices.contains(&device) {
+                changed_devices.push(device);
+            }
+        }
+        let streams_changed = events.iter().any(|x| match x.facility {
+            Facility::SinkInput | Facility::SourceOutput => x.change != Change::Changed,
+            _ => false,
+        });
+        if !self.ready {
+            return;
+        }
+        let mut lock_was_connected = [false, false];
+        if devices_changed {
+            self.reload_lock();
+            lock_was_connected = [AudioDeviceType::Source, AudioDeviceType::Sink]
+                .map(|x| self.is_lock_connected(&x));
+            self.handle_device_changes();
+        } else if !changed_devices.is_empty() {
+            self.update_levels(&changed_devices);
+        }
+        if streams_changed {
+            self.apply_app_rules();
+            self.update_recording();
+            self.update_apps();
+        }
+        if devices_changed {
+            self.enforce_lock(&AudioDeviceType::Source, !lock_was_connected[0]);
+            self.enforce_lock(&AudioDeviceType::Sink, !lock_was_connected[1]);
+        }
+    }
+
+    // Picks up volume, mute and state changes of devices without reloading
+    // everything, as they come on every step of a volume slider.
+    fn update_levels(&mut self, devices: &[(AudioDeviceType, u32)]) {
+        for (device_type, index) in devices {
+            let pulsewrapper = self.pulsewrapper.borrow();
+            let levels = match device_type {
+                AudioDeviceType::Source => pulsewrapper.get_source_levels(*index),
+                AudioDeviceType::Sink => pulsewrapper.get_sink_levels(*index),
+            };
+            drop(pulsewrapper);
+            let levels = match levels {
+                Some(levels) => levels,
+                None => continue,
+            };
+            let devices = match device_type {
+                AudioDeviceType::Source => &self.sources,
+                AudioDeviceType::Sink => &self.sinks,
+            };
+            let device = match devices.iter().find(|x| x.connected && x.index == *index) {
+                Some(device) => device,
+                None => continue,
+            };
+            let volume_changed = device.volume != levels.volume || device.muted != levels.mute;
+            if !volume_changed
+                && device.running == levels.running
+                && device.suspended == levels.suspended
+            {
+                continue;
+            }
+            let name = device.name.clone();
+            if let Some(device) = self.devices_mut(device_type).find(|x| x.name == name) {
+                device.volume = levels.volume;
+                device.muted = levels.mute;
+                device.running = levels.running;
+                device.suspended = levels.suspended;
+            }
+            if volume_changed {
+                self.remember_volume(device_type, &name);
+            }
         }
-        for sink in &mut config.sinks {
-            let connected = pa_sink_map.remove(&sink.name).is_some();
-            sinks.push(AudioDeviceState {
-                name: sink.name.clone(),
-                label: sink.label.clone(),
-                device_type: AudioDeviceType::Sink,
-                connected,
-                hidden: sink.hidden,
-                pulsewrapper: pulsewrapper.clone(),
+    }
+
+    // Keeps the volume and mute state of a default device for when it's
+    // selected again.
+    fn remember_volume(&self, device_type: &AudioDeviceType, name: &str) {
+        if name != self.default_of(device_type) {
+            return;
+        }
+        if let Some(device) = self.device(device_type, name) {
+            let state = VolumeState {
+                volume: device.volume,
+                mute: device.muted,
+            };
+            SavedVolumes::load().remember(std::iter::once((name.to_string(), state)));
+        }
+    }
+
+    fn handle_device_changes(&mut self) {
+        let connected_before = self.connected_devices();
+        let defaults_before = [AudioDeviceType::Source, AudioDeviceType::Sink].map(|x| {
+            let name = self.default_of(&x).to_string();
+            let label = self.label_of(&x, &name);
+            (x, name, label)
+        });
+        self.refresh();
+
+        let connected = self.connected_devices();
+        for (device_type, name, label) in defaults_before {
+            let device = (device_type.clone(), name);
+            if connected_before.contains(&device) && !connected.contains(&device) {
+                self.fall_back(&device_type, &label);
+            }
+        }
+        for (device_type, name) in connected {
+            if !connected_before.contains(&(device_type.clone(), name.clone())) {
+                self.switch_to_preferred(&device_type, &name);
+                if self.inputs_muted && device_type == AudioDeviceType::Source {
+                    self.mute_new_input(&name);
+                }
+            }
+        }
+    }
+
+    fn update_recording(&mut self) {
+        let streams = self.pulsewrapper.borrow().get_source_outputs();
+        let mut applications = streams
+            .iter()
+            .filter_map(|x| x.properties.get(APPLICATION_NAME_PROPERTY).cloned())
+            .collect::<Vec<_>>();
+        applications.sort();
+        applications.dedup();
+        if *self.recording != applications {
+            self.recording = Arc::new(applications);
+        }
+    }
+
+    // Counts the applications using each device.
+    fn update_apps(&mut self) {
+        let pulsewrapper = self.pulsewrapper.borrow();
+        let sink_inputs = pulsewrapper.get_sink_inputs();
+        let source_outputs = pulsewrapper.get_source_outputs();
+        drop(pulsewrapper);
+        for (device_type, streams) in [
+            (AudioDeviceType::Sink, sink_inputs),
+            (AudioDeviceType::Source, source_outputs),
+        ] {
+            for device in self.devices_mut(&device_type) {
+                let mut applications = streams
+                    .iter()
+                    .filter(|x| device.connected && x.device == device.index)
+                    .map(|x| {
+                        x.properties
+                            .get(APPLICATION_NAME_PROPERTY)
+                            .cloned()
+                            .unwrap_or_else(|| x.index.to_string())
+                    })
+                    .collect::<Vec<_>>();
+                applications.sort();
+                applications.dedup();
+                device.apps = applications.len();
+            }
+        }
+    }
+
+    // Mutes every input other than monitors, remembering which were muted
+    // already, or puts them all back the way they were.
+    pub fn mute_inputs(&mut self, mute: bool) -> Vec<String> {
+        let mut saved = SavedVolumes::load();
+        let mut failures = Vec::new();
+        if mute {
+            if saved.inputs_before_mute().is_some() {
+                self.inputs_muted = true;
+                return failures;
+            }
+            let inputs = self
+                .sources
+                .iter()
+                .filter(|x| x.connected && !is_monitor(&x.properties))
+                .map(|x| (x.name.clone(), x.muted))
+                .collect::<BTreeMap<_, _>>();
+            for name in inputs.keys() {
+                if let PulseWrapperError::Err = self.set_input_mute(name, true) {
+                    failures.push(format!(
+                        "Could not mute {}",
+                        self.label_of(&AudioDeviceType::Source, name)
+                    ));
+                }
+            }
+            saved.set_inputs_before_mute(Some(inputs));
+        } else {
+            let inputs = saved.inputs_before_mute().cloned().unwrap_or_default();
+            for (name, muted) in inputs {
+                let connected = self
+                    .device(&AudioDeviceType::Source, &name)
+                    .is_some_and(|x| x.connected);
+                if connected {
+                    if let PulseWrapperError::Err = self.set_input_mute(&name, muted) {
+                        failures.push(format!(
+                            "Could not unmute {}",
+                            self.label_of(&AudioDeviceType::Source, &name)
+                        ));
+                    }
+                }
+            }
+            saved.set_inputs_before_mute(None);
+        }
+        self.inputs_muted = mute;
+        failures
+    }
+
+    // Inputs that connect while all inputs are muted get muted too.
+    fn mute_new_input(&mut self, name: &str) {
+        let device = match self.device(&AudioDeviceType::Source, name) {
+            Some(device) if !is_monitor(&device.properties) => device,
+            _ => return,
+        };
+        let muted = device.muted;
+        let mut saved = SavedVolumes::load();
+        let mut inputs = saved.inputs_before_mute().cloned().unwrap_or_default();
+        if inputs.contains_key(name) && muted {
+            return;
+        }
+        inputs.entry(name.to_string()).or_insert(muted);
+        saved.set_inputs_before_mute(Some(inputs));
+        if let PulseWrapperError::Err = self.set_input_mute(name, true) {
+            self.log(format!(
+                "Could not mute {}",
+                self.label_of(&AudioDeviceType::Source, name)
+            ));
+        }
+    }
+
+    fn set_input_mute(&mut self, name: &str, mute: bool) -> PulseWrapperError {
+        let result = self.pulsewrapper.borrow_mut().set_source_mute(name, mute);
+        if let PulseWrapperError::Ok = result {
+            if let Some(device) = self
+                .devices_mut(&AudioDeviceType::Source)
+                .find(|x| x.name == name)
+            {
+                device.muted = mute;
+            }
+        }
+        result
+    }
+
+    // One line describing the defaults, for status bars.
+    pub fn status_line(&self) -> String {
+        let mut line = format!(
+            "Output: {} | Input: {}",
+            self.label_of(&AudioDeviceType::Sink, &self.default_sink),
+            self.label_of(&AudioDeviceType::Source, &self.default_source),
+        );
+        if self.inputs_muted {
+            line.push_str(" (all muted)");
+        }
+        if !self.recording.is_empty() {
+            line.push_str(&format!(" | Recording: {}", self.recording.join(", ")));
+        }
+        line
+    }
+
+    // While a stream matches an app rule, the first matching rule decides the
+    // defaults and matching streams are moved to its devices. The defaults from
+    // before are restored once the last matching stream is gone.
+    fn apply_app_rules(&mut self) {
+        if self.config.app_rules.is_empty() {
+            return;
+        }
+        let pulsewrapper = self.pulsewrapper.borrow();
+        let sink_inputs = pulsewrapper.get_sink_inputs();
+        let source_outputs = pulsewrapper.get_source_outputs();
+        drop(pulsewrapper);
+
+        let rules = self.config.app_rules.clone();
+        let matching = |rule: &AppRule| {
+            sink_inputs
+                .iter()
+                .chain(source_outputs.iter())
+                .find(|x| rule.matches(&x.properties))
+        };
+        let active = rules.iter().position(|x| matching(x).is_some());
+
+        let rule = match active {
+            Some(i) => &rules[i],
+            None => {
+                let (source, sink) = std::mem::take(&mut self.app_rule_defaults);
+                if let Some(source) = source {
+                    self.restore_default(&AudioDeviceType::Source, &source);
+                }
+                if let Some(sink) = sink {
+                    self.restore_default(&AudioDeviceType::Sink, &sink);
+                }
+                self.active_app_rule = None;
+                return;
+            }
+        };
+
+        if self.active_app_rule != active {
+            let application = matching(rule)
+                .and_then(|x| x.properties.get(APPLICATION_NAME_PROPERTY))
+                .cloned()
+                .unwrap_or_default();
+            for (device_type, device) in [
+                (AudioDeviceType::Sink, &rule.sink),
+                (AudioDeviceType::Source, &rule.source),
+            ] {
+                let name = match device
+                    .as_ref()
+                    .and_then(|x| self.resolve_device_of(&device_type, x))
+                {
+                    Some(name) => name,
+                    None => continue,
+                };
+                if name != self.default_of(&device_type) {
+                    let previous = self.default_of(&device_type).to_string();
+                    if let PulseWrapperError::Err = self.set_default(&device_type, &name) {
+                        self.log(format!(
+                            "Could not switch to {}",
+                            self.label_of(&device_type, &name)
+                        ));
+                        continue;
+                    }
+                    let saved = match device_type {
+                        AudioDeviceType::Source => &mut self.app_rule_defaults.0,
+                        AudioDeviceType::Sink => &mut self.app_rule_defaults.1,
+                    };
+                    saved.get_or_insert(previous);
+                    let message = format!(
+                        "Switched to {} because {} is running",
+                        self.label_of(&device_type, &name),
+                        application,
+                    );
+                    self.announce(DefaultChange::Automatic, &device_type, &name, message);
+                }
+            }
+            self.active_app_rule = active;
+        }
+
+        // Streams of every matching rule go to that rule's devices.
+        for rule in &rules {
+            self.move_app_streams(rule, &AudioDeviceType::Sink, &sink_inputs);
+            self.move_app_streams(rule, &AudioDeviceType::Source, &source_outputs);
+        }
+    }
+
+    fn move_app_streams(
+        &self,
+        rule: &AppRule,
+        device_type: &AudioDeviceType,
+        streams: &[StreamInfo],
+    ) {
+        let device = match device_type {
+            AudioDeviceType::Source => &rule.source,
+            AudioDeviceType::Sink => &rule.sink,
+        };
+        let target = match device
+            .as_ref()
+            .and_then(|x| self.resolve_device_of(device_type, x))
+            .and_then(|x| self.device(device_type, &x))
+        {
+            Some(target) => target,
+            None => return,
+        };
+        let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+        for stream in streams {
+            if !rule.matches(&stream.properties) || stream.device == target.index {
+                continue;
+            }
+            let result = match device_type {
+                AudioDeviceType::Source => {
+                    pulsewrapper.move_source_output(stream.index, &target.name)
+                }
+                AudioDeviceType::Sink => pulsewrapper.move_sink_input(stream.index, &target.name),
+            };
+            if let PulseWrapperError::Err = result {
+                eprintln!("Could not move stream {} to {}", stream.index, target.name);
+            }
+        }
+    }
+
+    fn restore_default(&mut self, device_type: &AudioDeviceType, name: &str) {
+        let connected = self.device(device_type, name).is_some_and(|x| x.connected);
+        if !connected || name == self.default_of(device_type) {
+            return;
+        }
+        if let PulseWrapperError::Ok = self.set_default(device_type, name) {
+            let message = format!(
+                "Switched back to {} after the last matching application closed",
+                self.label_of(device_type, name),
+            );
+            self.announce(DefaultChange::Automatic, device_type, name, message);
+        }
+    }
+
+    fn lock_of(&self, device_type: &AudioDeviceType) -> Option<&String> {
+        match device_type {
+            AudioDeviceType::Source => self.locked_source.as_ref(),
+            AudioDeviceType::Sink => self.locked_sink.as_ref(),
+        }
+    }
+
+    pub fn is_locked(&self, device_type: &AudioDeviceType) -> bool {
+        self.lock_of(device_type).is_some()
+    }
+
+    // Locks to the current default, or unlocks.
+    pub fn lock(&mut self, device_type: &AudioDeviceType, locked: bool) {
+        let lock = Some(self.default_of(device_type).to_string()).filter(|_| locked);
+        match device_type {
+            AudioDeviceType::Source => self.locked_source = lock.clone(),
+            AudioDeviceType::Sink => self.locked_sink = lock.clone(),
+        }
+        AppConfig::update(|config| match device_type {
+            AudioDeviceType::Source => config.locked_source = lock,
+            AudioDeviceType::Sink => config.locked_sink = lock,
+        });
+    }
+
+    // Lets other applications change the defaults for a while.
+    pub fn pause_lock(&mut self) {
+        let minutes = self.config.lock_override_minutes;
+        let until = now() + minutes * 60;
+        self.lock_paused_until = Some(until);
+        AppConfig::update(|config| config.lock_paused_until = Some(until));
+        self.log(format!(
+            "Other applications may change defaults for {} minutes",
+            minutes
+        ));
+    }
+
+    pub fn is_lock_paused(&self) -> bool {
+        self.lock_paused_until.is_some_and(|x| now() < x)
+    }
+
+    // Picks up locks and pauses set by another instance, such as the popup or
+    // `audio-select lock` while `watch` runs.
+    fn reload_lock(&mut self) {
+        match confy::load::<AppConfig>("audio-select", None) {
+            Ok(config) => {
+                self.locked_source = config.locked_source;
+                self.locked_sink = config.locked_sink;
+                self.lock_paused_until = config.lock_paused_until;
+            }
+            Err(e) => eprintln!("Failed to load config: {}", e),
+        }
+    }
+
+    fn is_lock_connected(&self, device_type: &AudioDeviceType) -> bool {
+        self.lock_of(device_type)
+            .and_then(|x| self.device(device_type, x))
+            .is_some_and(|x| x.connected)
+    }
+
+    // Reverts a default another client changed away from the locked device, or
+    // that pulseaudio moved while the locked device was disconnected.
+    fn enforce_lock(&mut self, device_type: &AudioDeviceType, reconnected: bool) {
+        let locked = match self.lock_of(device_type) {
+            Some(locked) => locked.clone(),
+            None => return,
+        };
+        if self.active_app_rule.is_some() {
+            return;
+        }
+        let current = self.default_of(device_type).to_string();
+        let connected = self
+            .device(device_type, &locked)
+            .is_some_and(|x| x.connected);
+        if current == locked || !connected || self.is_lock_paused() {
+            return;
+        }
+        if let PulseWrapperError::Err = self.set_default(device_type, &locked) {
+            self.log(format!(
+                "Could not switch back to {}",
+                self.label_of(device_type, &locked)
+            ));
+            return;
+        }
+        let message = if reconnected {
+            format!(
+                "Switched back to {} as it reconnected",
+                self.label_of(device_type, &locked)
+            )
+        } else {
+            format!(
+                "Switched back to {} after another application changed it to {}",
+                self.label_of(device_type, &locked),
+                self.label_of(device_type, &current),
+            )
+        };
+        self.announce(DefaultChange::Lock, device_type, &locked, message);
+    }
+
+    // Replaces whatever pulseaudio picked after the default went away with the
+    // first connected device from the fallback list. Hidden devices are skipped,
+    // and without a fallback pulseaudio's pick is only announced.
+    fn fall_back(&mut self, device_type: &AudioDeviceType, lost: &str) {
+        let (fallbacks, devices) = match device_type {
+            AudioDeviceType::Source => (&self.config.fallback_sources, &self.sources),
+            AudioDeviceType::Sink => (&self.config.fallback_sinks, &self.sinks),
+        };
+        let choice = fallbacks.iter().find_map(|reference| {
+            devices
+                .iter()
+                .find(|x| x.is_visible() && x.is_called(reference))
+                .map(|x| x.name.clone())
+        });
+        let current = self.default_of(device_type).to_string();
+        let name = match choice {
+            Some(name) if name != current => {
+                if let PulseWrapperError::Err = self.switch_default(device_type, &name, true) {
+                    self.log(format!(
+                        "Could not fall back to {}",
+                        self.label_of(device_type, &name)
+                    ));
+                    return;
+                }
+                name
+            }
+            // Without a usable fallback whatever pulseaudio picked is announced
+            _ if self
+                .device(device_type, &current)
+                .is_some_and(|x| x.connected) =>
+            {
+                current
+            }
+            _ => return,
+        };
+        let message = format!(
+            "Fell back to {} because {} disconnected",
+            self.label_of(device_type, &name),
+            lost,
+        );
+        self.announce(DefaultChange::FallBack, device_type, &name, message);
+    }
+
+    fn connected_devices(&self) -> Vec<(AudioDeviceType, String)> {
+        self.sources
+            .iter()
+            .chain(self.sinks.iter())
+            .filter(|x| x.connected)
+            .map(|x| (x.device_type.clone(), x.name.clone()))
+            .collect()
+    }
+
+    fn rank(&self, device_type: &AudioDeviceType, name: &str) -> Option<usize> {
+        let preferred = match device_type {
+            AudioDeviceType::Source => &self.config.preferred_sources,
+            AudioDeviceType::Sink => &self.config.preferred_sinks,
+        };
+        let device = self.device(device_type, name)?;
+        preferred.iter().position(|x| device.is_called(x))
+    }
+
+    // Switches to a device that just connected if it ranks above the default.
+    fn switch_to_preferred(&mut self, device_type: &AudioDeviceType, name: &str) {
+        let rank = match self.rank(device_type, name) {
+            Some(rank) => rank,
+            None => return,
+        };
+        let current = self.default_of(device_type).to_string();
+        if current == name || self.rank(device_type, &current).is_some_and(|x| x <= rank) {
+            return;
+        }
+        if self.is_locked(device_type) && !self.is_lock_paused() {
+            return;
+        }
+        if let PulseWrapperError::Err = self.switch_default(device_type, name, true) {
+            self.log(format!(
+                "Could not switch to {}",
+                self.label_of(device_type, name)
+            ));
+            return;
+        }
+        let message = format!(
+            "Switched from {} to {} because it connected and is preferred",
+            self.label_of(device_type, &current),
+            self.label_of(device_type, name),
+        );
+        self.announce(DefaultChange::Automatic, device_type, name, message);
+        if self.config.move_streams_on_switch {
+            self.move_streams(device_type, name);
+        }
+    }
+
+    pub fn move_streams(&mut self, device_type: &AudioDeviceType, name: &str) {
+        let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+        let result = match device_type {
+            AudioDeviceType::Source => pulsewrapper.move_source_outputs(name),
+            AudioDeviceType::Sink => pulsewrapper.move_sink_inputs(name),
+        };
+        drop(pulsewrapper);
+        if let PulseWrapperError::Err = result {
+            self.log(format!("Could not move every stream to {}", name));
+        }
+    }
+
+    pub fn default_of(&self, device_type: &AudioDeviceType) -> &str {
+        match device_type {
+            AudioDeviceType::Source => &self.default_source,
+            AudioDeviceType::Sink => &self.default_sink,
+        }
+    }
+
+    fn label_of(&self, device_type: &AudioDeviceType, name: &str) -> String {
+        self.device(device_type, name)
+            .map_or(name, |x| x.display_label())
+            .to_string()
+    }
+
+    // Logs a change of the default and shows a notification if the user wants
+    // one for this kind of change.
+    fn announce(
+        &mut self,
+        change: DefaultChange,
+        device_type: &AudioDeviceType,
+        name: &str,
+        message: String,
+    ) {
+        let notify = match change {
+            DefaultChange::Selected => self.notify_on_select,
+            DefaultChange::Automatic => self.notify_on_automatic,
+            DefaultChange::FallBack => self.notify_on_fallback,
+            DefaultChange::Lock => self.notify_on_lock,
+        };
+        if notify {
+            let summary = match device_type {
+                AudioDeviceType::Source => format!("Input: {}", self.label_of(device_type, name)),
+                AudioDeviceType::Sink => format!("Output: {}", self.label_of(device_type, name)),
+            };
+            // The icon shown in the popup, else the configured or reported name
+            let device = self.device(device_type, name);
+            let icon = device
+                .map(|x| &x.icon_path)
+                .filter(|x| !x.is_empty())
+                .or_else(|| device.map(|x| &x.icon).filter(|x| !x.is_empty()))
+                .or_else(|| device.and_then(|x| x.properties.get(ICON_NAME_PROPERTY)))
+                .map_or_else(
+                    || match device_type {
+                        AudioDeviceType::Source => "audio-input-microphone",
+                        AudioDeviceType::Sink => "audio-speakers",
+                    },
+                    String::as_str,
+                );
+            notifications::show(&summary, &message, icon);
+        }
+        if change != DefaultChange::Selected {
+            self.log(message);
+        }
+    }
+
+    // Explains a change the app made on its own, on stderr and in the popup.
+    fn log(&mut self, message: String) {
+        eprintln!("{}", message);
+        self.status = message;
+    }
+
+    // Applies every part of a scene that can be applied and returns a message
+    // for each part that couldn't, e.g. because a device isn't connected.
+    pub fn apply_scene(&mut self, name: &str) -> Vec<String> {
+        let scene = match self.config.scenes.iter().find(|x| x.name == name) {
+            Some(scene) => scene.clone(),
+            None => return vec![format!("There is no scene called {}", name)],
+        };
+        let mut failures = Vec::new();
+
+        // Profiles come first since they add and remove sinks and sources.
+        if !scene.profiles.is_empty() {
+            let cards = self.pulsewrapper.borrow().get_cards();
+            for (card, profile) in &scene.profiles {
+                let card = match cards
+                    .iter()
+                    .find(|x| x.name == *card || x.description == *card)
+                {
+                    Some(card) => card,
+                    None => {
+                        failures.push(format!("{} is not present", card));
+                        continue;
+                    }
+                };
+                let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+                if let PulseWrapperError::Err = pulsewrapper.set_card_profile(&card.name, profile) {
+                    failures.push(format!(
+                        "Could not set profile {} on {}",
+                        profile, card.name
+                    ));
+                }
+            }
+            self.refresh();
+        }
+
+        for (device, port) in &scene.ports {
+            self.apply_to_device(
+                device,
+                "port",
+                &mut failures,
+                |pulsewrapper, device_type, name| match device_type {
+                    AudioDeviceType::Source => pulsewrapper.set_source_port(name, port),
+                    AudioDeviceType::Sink => pulsewrapper.set_sink_port(name, port),
+                },
+            );
+        }
+        for (device, volume) in &scene.volumes {
+            self.apply_to_device(
+                device,
+                "volume",
+                &mut failures,
+                |pulsewrapper, device_type, name| match device_type {
+                    AudioDeviceType::Source => pulsewrapper.set_source_volume(name, *volume),
+                    AudioDeviceType::Sink => pulsewrapper.set_sink_volume(name, *volume),
+                },
+            );
+        }
+        for (device, mute) in &scene.mute {
+            self.apply_to_device(
+                device,
+                "mute",
+                &mut failures,
+                |pulsewrapper, device_type, name| match device_type {
+                    AudioDeviceType::Source => pulsewrapper.set_source_mute(name, *mute),
+                    AudioDeviceType::Sink => pulsewrapper.set_sink_mute(name, *mute),
+                },
+            );
+        }
+
+        let defaults = [
+            (AudioDeviceType::Sink, &scene.sink),
+            (AudioDeviceType::Source, &scene.source),
+        ];
+        // A pair only follows when the scene leaves its direction alone.
+        let sets_both = scene.sink.is_some() && scene.source.is_some();
+        for (device_type, device) in defaults {
+            let device = match device {
+                Some(device) => device,
+                None => continue,
+            };
+            match self.resolve_device_of(&device_type, device) {
+                Some(name) => {
+                    failures.extend(self.select_device_paired(&device_type, &name, !sets_both))
+                }
+                None => failures.push(format!("{} is not connected", device)),
+            }
+        }
+
+        self.refresh();
+        failures
+    }
+
+    fn apply_to_device(
+        &self,
+        device: &str,
+        what: &str,
+        failures: &mut Vec<String>,
+        f: impl FnOnce(&mut PulseWrapper, &AudioDeviceType, &str) -> PulseWrapperError,
+    ) {
+        match self.resolve_device(device) {
+            Some((device_type, name)) => {
+                let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+                if let PulseWrapperError::Err = f(&mut pulsewrapper, &device_type, &name) {
+                    failures.push(format!("Could not set {} of {}", what, device));
+                }
+            }
+            None => failures.push(format!("{} is not connected", device)),
+        }
+    }
+
+    // Moves a device by offset places in the manual order.
+    pub fn move_device(&mut self, device_type: &AudioDeviceType, name: &str, offset: isize) {
+        let devices = match device_type {
+            AudioDeviceType::Source => Arc::make_mut(&mut self.sources),
+            AudioDeviceType::Sink => Arc::make_mut(&mut self.sinks),
+        };
+        if let Some(from) = devices.iter().position(|x| x.name == name) {
+            let to = num::clamp(from as isize + offset, 0, devices.len() as isize - 1) as usize;
+            let device = devices.remove(from);
+            devices.insert(to, device);
+        }
+    }
+
+    // Devices in the order the popup shows them: favourites first, then by
+    // the selected sort mode.
+    pub fn sorted_devices(&self, device_type: &AudioDeviceType) -> Vec<AudioDeviceState> {
+        let mut devices = match device_type {
+            AudioDeviceType::Source => self.sources.to_vec(),
+            AudioDeviceType::Sink => self.sinks.to_vec(),
+        };
+        match self.sort_mode {
+            SortMode::Manual => {}
+            SortMode::Alphabetical => {
+                devices.sort_by_cached_key(|x| x.display_label().to_lowercase())
+            }
+            SortMode::MostUsed => devices.sort_by_key(|x| std::cmp::Reverse(x.use_count)),
+            SortMode::RecentlyConnected => {
+                devices.sort_by_key(|x| std::cmp::Reverse((x.connected, x.index)))
+            }
+        }
+        devices.sort_by_key(|x| !x.favourite);
+        devices
+    }
+
+    // Visible devices split into sections by the selected grouping. Without
+    // grouping there is a single untitled section.
+    pub fn grouped_devices(&self, device_type: &AudioDeviceType) -> Vec<DeviceGroup> {
+        let prefix = match device_type {
+            AudioDeviceType::Source => "source",
+            AudioDeviceType::Sink => "sink",
+        };
+        let mut groups: Vec<DeviceGroup> = Vec::new();
+        for device in self.sorted_devices(device_type) {
+            if !device.is_visible() || self.search_score(&device).is_none() {
+                continue;
+            }
+            let (key, title) = self.group_of(&device);
+            let key = format!("{}/{}", prefix, key);
+            match groups.iter_mut().find(|x| x.key == key) {
+                Some(group) => Arc::make_mut(&mut group.devices).push(device),
+                None => groups.push(DeviceGroup {
+                    collapsed: !title.is_empty()
+                        && self.search.is_empty()
+                        && self.collapsed_groups.contains(&key),
+                    key,
+                    title,
+                    devices: Arc::new(vec![device]),
+                }),
+            }
+        }
+        if self.group_by == GroupBy::Custom {
+            groups.sort_by_key(|group| {
+                self.config
+                    .groups
+                    .iter()
+                    .position(|x| x.name == group.title)
+                    .unwrap_or(usize::MAX)
             });
         }
+        groups
+    }
+
+    fn group_of(&self, device: &AudioDeviceState) -> (String, String) {
+        match self.group_by {
+            GroupBy::Nothing => (String::new(), String::new()),
+            GroupBy::Card if device.card.is_empty() => {
+                (String::from("card:"), String::from("Virtual"))
+            }
+            GroupBy::Card => (format!("card:{}", device.card), device.card.clone()),
+            GroupBy::Bus => {
+                let title = match bus_of(&device.properties) {
+                    Some("usb") => String::from("USB"),
+                    Some("pci") => String::from("PCI"),
+                    Some("bluetooth") => String::from("Bluetooth"),
+                    Some(bus) => bus.to_uppercase(),
+                    None => String::from("Virtual"),
+                };
+                (format!("bus:{}", title), title)
+            }
+            GroupBy::Custom => self
+                .config
+                .groups
+                .iter()
+                .find(|group| {
+                    group
+                        .rules
+                        .iter()
+                        .any(|x| x.matches(&device.name, &device.properties))
+                })
+                .map_or_else(
+                    || (String::from("group:"), String::from("Other")),
+                    |group| (format!("group:{}", group.name), group.name.clone()),
+                ),
+        }
+    }
+
+    pub fn min_window_size(&self) -> Size {
+        self.config.min_window_size.into()
+    }
+
+    pub fn max_window_size(&self) -> Size {
+        self.config.max_window_size.into()
+    }
+
+    // Switches between the popup and the pinned mixer, remembered right away.
+    pub fn set_pinned(&mut self, pinned: bool) {
+        self.pinned = pinned;
+        AppConfig::update(|config| config.pinned = pinned);
+    }
+
+    pub fn key_bindings(&self) -> &KeyBindings {
+        &self.config.keys
+    }
+
+    // Devices in the order the popup shows them, skipping collapsed sections.
+    fn popup_order(&self, device_type: &AudioDeviceType) -> Vec<(AudioDeviceType, String)> {
+        self.grouped_devices(device_type)
+            .iter()
+            .filter(|x| !x.collapsed)
+            .flat_map(|x| x.devices.iter())
+            .map(|x| (x.device_type.clone(), x.name.clone()))
+            .collect()
+    }
+
+    fn set_focus(&mut self, focus: Option<(AudioDeviceType, String)>) {
+        self.focus = focus;
+        self.mark_devices();
+    }
+
+    // Copies the keyboard highlight and the rename in progress to the devices.
+    fn mark_devices(&mut self) {
+        let focus = self.focus.clone();
+        let renaming = self.renaming.clone();
+        for devices in [&mut self.sources, &mut self.sinks] {
+            for device in Arc::make_mut(devices) {
+                device.focused = focus
+                    .as_ref()
+                    .is_some_and(|x| x.0 == device.device_type && x.1 == device.name);
+                device.renaming = renaming
+                    .as_ref()
+                    .is_some_and(|x| x.0 == device.device_type && x.1 == device.name);
+            }
+        }
+    }
 
-        for (source, label) in pa_source_map {
-            sources.push(AudioDeviceState {
-                name: source.clone(),
-                label: label.clone(),
-                device_type: AudioDeviceType::Source,
-                connected: true,
-                hidden: false,
-                pulsewrapper: pulsewrapper.clone(),
+    // Moves the highlight through inputs and then outputs, wrapping around.
+    pub fn move_focus(&mut self, offset: isize) {
+        let mut order = self.popup_order(&AudioDeviceType::Source);
+        order.extend(self.popup_order(&AudioDeviceType::Sink));
+        if order.is_empty() {
+            return;
+        }
+        let len = order.len() as isize;
+        let next = match self
+            .focus
+            .as_ref()
+            .and_then(|x| order.iter().position(|y| y == x))
+        {
+            Some(i) => (i as isize + offset).rem_euclid(len),
+            None if offset > 0 => 0,
+            None => len - 1,
+        };
+        self.set_focus(Some(order[next as usize].clone()));
+    }
+
+    // The best score of the search over the label, name and some properties
+    // of a device, or None if it doesn't match.
+    fn search_score(&self, device: &AudioDeviceState) -> Option<i64> {
+        if self.search.trim().is_empty() {
+            return Some(0);
+        }
+        let properties = SEARCH_PROPERTIES
+            .iter()
+            .filter_map(|x| device.properties.get(*x).map(String::as_str));
+        [device.display_label(), device.name.as_str()]
+            .into_iter()
+            .chain(properties)
+            .filter_map(|x| fuzzy_score(&self.search, x))
+            .max()
+    }
+
+    // Highlights the best match so that Enter selects it, the first one in the
+    // popup on a tie.
+    pub fn search_changed(&mut self) {
+        if self.search.trim().is_empty() {
+            self.set_focus(None);
+            return;
+        }
+        let mut order = self.popup_order(&AudioDeviceType::Source);
+        order.extend(self.popup_order(&AudioDeviceType::Sink));
+        let best = order
+            .into_iter()
+            .filter_map(|(device_type, name)| {
+                let score = self.search_score(self.device(&device_type, &name)?)?;
+                Some((score, device_type, name))
+            })
+            .min_by_key(|x| std::cmp::Reverse(x.0))
+            .map(|(_, device_type, name)| (device_type, name));
+        self.set_focus(best);
+    }
+
+    // Highlights the first device of the other section.
+    pub fn focus_next_section(&mut self) {
+        let device_type = self
+            .focus
+            .as_ref()
+            .map_or(AudioDeviceType::Source, |x| x.0.other());
+        let first = self
+            .popup_order(&device_type)
+            .into_iter()
+            .next()
+            .or_else(|| self.popup_order(&device_type.other()).into_iter().next());
+        if first.is_some() {
+            self.set_focus(first);
+        }
+    }
+
+    pub fn select_focused(&mut self) -> Vec<String> {
+        match self.focus.clone() {
+            Some((device_type, name)) => self.select_device(&device_type, &name),
+            None => Vec::new(),
+        }
+    }
+
+    // Selects the nth device of a section, counting from 1.
+    pub fn select_nth(&mut self, device_type: &AudioDeviceType, n: usize) -> Vec<String> {
+        match self.popup_order(device_type).get(n.wrapping_sub(1)) {
+            Some((device_type, name)) => self.select_device(device_type, name),
+            None => Vec::new(),
+        }
+    }
+
+    // The highlighted device, or the default output if nothing is.
+    fn focused_or_default(&self) -> (AudioDeviceType, String) {
+        self.focus
+            .clone()
+            .unwrap_or_else(|| (AudioDeviceType::Sink, self.default_sink.clone()))
+    }
+
+    pub fn toggle_mute_focused(&mut self) -> Vec<String> {
+        let (device_type, name) = self.focused_or_default();
+        let muted = match self.device(&device_type, &name) {
+            Some(device) => device.muted,
+            None => return Vec::new(),
+        };
+        self.set_mute(&device_type, &name, !muted)
+    }
+
+    pub fn set_mute(
+        &mut self,
+        device_type: &AudioDeviceType,
+        name: &str,
+        mute: bool,
+    ) -> Vec<String> {
+        let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+        let result = match device_type {
+            AudioDeviceType::Source => pulsewrapper.set_source_mute(name, mute),
+            AudioDeviceType::Sink => pulsewrapper.set_sink_mute(name, mute),
+        };
+        drop(pulsewrapper);
+        if let PulseWrapperError::Err = result {
+            return vec![format!(
+                "Could not mute {}",
+                self.label_of(device_type, name)
+            )];
+        }
+        if let Some(device) = self.devices_mut(device_type).find(|x| x.name == name) {
+            device.muted = mute;
+        }
+        self.remember_volume(device_type, name);
+        Vec::new()
+    }
+
+    // Changes the volume of the highlighted device by a number of steps.
+    pub fn change_volume_focused(&mut self, steps: i32) -> Vec<String> {
+        let (device_type, name) = self.focused_or_default();
+        let volume = match self.device(&device_type, &name) {
+            Some(device) => device.volume as i32,
+            None => return Vec::new(),
+        };
+        let volume = (volume + steps * self.config.volume_step as i32)
+            .clamp(0, MAX_VOLUME_PERCENT as i32) as u32;
+        self.set_volume(&device_type, &name, volume)
+    }
+
+    pub fn set_volume(
+        &mut self,
+        device_type: &AudioDeviceType,
+        name: &str,
+        volume: u32,
+    ) -> Vec<String> {
+        // Pulseaudio would be given no more than this anyway, so the state
+        // doesn't show a volume the device doesn't have.
+        let volume = volume.min(MAX_VOLUME_PERCENT);
+        let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+        let result = match device_type {
+            AudioDeviceType::Source => pulsewrapper.set_source_volume(name, volume),
+            AudioDeviceType::Sink => pulsewrapper.set_sink_volume(name, volume),
+        };
+        drop(pulsewrapper);
+        if let PulseWrapperError::Err = result {
+            return vec![format!(
+                "Could not change the volume of {}",
+                self.label_of(device_type, name)
+            )];
+        }
+        if let Some(device) = self.devices_mut(device_type).find(|x| x.name == name) {
+            device.volume = volume;
+        }
+        self.remember_volume(device_type, name);
+        Vec::new()
+    }
+
+    // The ports of a device and the profiles of its card, for the device menu.
+    pub fn choices_of(&self, device_type: &AudioDeviceType, name: &str) -> DeviceChoices {
+        let pulsewrapper = self.pulsewrapper.borrow();
+        let devices = match device_type {
+            AudioDeviceType::Source => pulsewrapper.get_sources(),
+            AudioDeviceType::Sink => pulsewrapper.get_sinks(),
+        };
+        drop(pulsewrapper);
+        let mut choices = DeviceChoices::default();
+        if let Some(device) = devices.into_iter().find(|x| x.name == name) {
+            choices.ports = device.ports;
+            choices.active_port = device.active_port;
+        }
+        if let Some(card) = self.card_of(device_type, name) {
+            choices.profiles = card.profiles;
+            choices.active_profile = card.active_profile;
+        }
+        choices
+    }
+
+    // Everything the server reports about a device, as text for the inspector.
+    pub fn inspect(&self, device_type: &AudioDeviceType, name: &str) -> Option<String> {
+        let pulsewrapper = self.pulsewrapper.borrow();
+        let devices = match device_type {
+            AudioDeviceType::Source => pulsewrapper.get_sources(),
+            AudioDeviceType::Sink => pulsewrapper.get_sinks(),
+        };
+        let device = devices.into_iter().find(|x| x.name == name)?;
+        let module = device
+            .owner_module
+            .map(|x| (x, pulsewrapper.get_module_name(x).unwrap_or_default()));
+        drop(pulsewrapper);
+        let card = self.card_of(device_type, name);
+        let milliseconds = |x: u64| format!("{:.1} ms", x as f64 / 1000.0);
+
+        let mut lines = vec![
+            format!("Name: {}", device.name),
+            format!("Description: {}", device.description),
+            format!("Index: {}", device.index),
+            format!("Driver: {}", device.driver),
+            match module {
+                Some((index, name)) => format!("Owner module: {} ({})", index, name),
+                None => String::from("Owner module: none"),
+            },
+            format!("Sample spec: {}", device.sample_spec),
+            format!("Channel map: {}", device.channel_map),
+            format!(
+                "Latency: {} (configured {})",
+                milliseconds(device.latency),
+                milliseconds(device.configured_latency)
+            ),
+            format!(
+                "Volume: {}%{}",
+                device.volume,
+                if device.mute { ", muted" } else { "" }
+            ),
+            match card {
+                Some(card) => format!(
+                    "Card: {} ({}), profile {}",
+                    card.name,
+                    card.description,
+                    card.active_profile.as_deref().unwrap_or("none")
+                ),
+                None => String::from("Card: none"),
+            },
+            String::from("Ports:"),
+        ];
+        for port in &device.ports {
+            let active = device.active_port.as_ref() == Some(&port.name);
+            lines.push(format!(
+                "  {} {}: {}{}",
+                if active { "*" } else { " " },
+                port.name,
+                port.description,
+                if port.available { "" } else { " (unavailable)" }
+            ));
+        }
+        lines.push(String::from("Properties:"));
+        for (key, value) in &device.properties {
+            lines.push(format!("  {} = {}", key, value));
+        }
+        Some(lines.join("\n"))
+    }
+
+    pub fn set_port(
+        &mut self,
+        device_type: &AudioDeviceType,
+        name: &str,
+        port: &str,
+    ) -> Vec<String> {
+        let mut pulsewrapper = self.pulsewrapper.borrow_mut();
+        let result = match device_type {
+            AudioDeviceType::Source => pulsewrapper.set_source_port(name, port),
+            AudioDeviceType::Sink => pulsewrapper.set_sink_port(name, port),
+        };
+        drop(pulsewrapper);
+        match result {
+            PulseWrapperError::Ok => Vec::new(),
+            PulseWrapperError::Err => vec![format!(
+                "Could not set port {} on {}",
+                port,
+                self.label_of(device_type, name)
+            )],
+        }
+    }
+
+    // Sets the profile of a device's card, keeping the device the default if
+    // it was.
+    pub fn set_profile(
+        &mut self,
+        device_type: &AudioDeviceType,
+        name: &str,
+        profile: &str,
+    ) -> Vec<String> {
+        let was_default = self.default_of(device_type) == name;
+        let mut failures = Vec::new();
+        let renamed = self.set_device_profile(device_type, name, profile, &mut failures);
+        if let (true, Some(renamed)) = (was_default, renamed) {
+            if let PulseWrapperError::Err = self.set_default(device_type, &renamed) {
+                failures.push(format!(
+                    "Could not select {} again",
+                    self.label_of(device_type, &renamed)
+                ));
+            }
+        }
+        failures
+    }
+
+    pub fn toggle_favourite(&mut self, device_type: &AudioDeviceType, name: &str) {
+        let favourite = self
+            .devices_mut(device_type)
+            .find(|x| x.name == name)
+            .map(|x| {
+                x.favourite = !x.favourite;
+                x.favourite
             });
+        if let Some(favourite) = favourite {
+            self.save_device(device_type, name, |x| x.favourite = favourite);
+        }
+    }
+
+    pub fn hide_device(&mut self, device_type: &AudioDeviceType, name: &str) {
+        if let Some(device) = self.devices_mut(device_type).find(|x| x.name == name) {
+            device.hidden = true;
         }
-        for (sink, label) in pa_sink_map {
-            sinks.push(AudioDeviceState {
-                name: sink.clone(),
-                label: label.clone(),
-                device_type: AudioDeviceType::Sink,
-                connected: true,
-                hidden: false,
-                pulsewrapper: pulsewrapper.clone(),
+        self.save_device(device_type, name, |x| x.hidden = true);
+    }
+
+    // Saves a change made to a device from the popup right away, both to disk
+    // and as what Revert goes back to. Edits pending in the settings stay
+    // unsaved until they're applied.
+    fn save_device(
+        &mut self,
+        device_type: &AudioDeviceType,
+        name: &str,
+        change: impl Fn(&mut AudioDeviceConfig),
+    ) {
+        let device = match self.device(device_type, name) {
+            Some(device) => device.clone(),
+            None => return,
+        };
+        change(Arc::make_mut(&mut self.config).entry_mut(&device));
+        AppConfig::update(|config| change(config.entry_mut(&device)));
+    }
+
+    // Shows a text box for the label in place of the device's button, starting
+    // from the label it is shown with.
+    pub fn start_rename(&mut self, device_type: &AudioDeviceType, name: &str) {
+        self.finish_rename();
+        let device = match self.devices_mut(device_type).find(|x| x.name == name) {
+            Some(device) => device,
+            None => return,
+        };
+        let label = device.label.clone();
+        device.label = device.display_label().to_string();
+        self.renaming = Some((device_type.clone(), name.to_string(), label));
+        self.mark_devices();
+    }
+
+    pub fn is_renaming(&self) -> bool {
+        self.renaming.is_some()
+    }
+
+    // Keeps the new label, going back to the automatic one when it's cleared
+    // or left as it was.
+    pub fn finish_rename(&mut self) {
+        let (device_type, name, _) = match self.renaming.take() {
+            Some(x) => x,
+            None => return,
+        };
+        let label = self
+            .devices_mut(&device_type)
+            .find(|x| x.name == name)
+            .map(|x| {
+                x.label = x.label.trim().to_string();
+                if x.label == x.default_label {
+                    x.label.clear();
+                }
+                x.label.clone()
             });
+        if let Some(label) = label {
+            self.save_device(&device_type, &name, |x| x.label = label.clone());
         }
+        self.mark_devices();
+    }
 
-        AppState {
-            ready: true,
-            not_ready_string: String::new(),
-            sources: Arc::new(sources),
-            sinks: Arc::new(sinks),
-            default_source,
-            default_sink,
-            pulsewrapper,
-            use_dark_theme: config.use_dark_theme,
-            close_on_leave: true,
+    pub fn cancel_rename(&mut self) {
+        let (device_type, name, label) = match self.renaming.take() {
+            Some(x) => x,
+            None => return,
+        };
+        if let Some(device) = self.devices_mut(&device_type).find(|x| x.name == name) {
+            device.label = label;
+        }
+        self.mark_devices();
+    }
+
+    pub fn toggle_group(&mut self, key: &str) {
+        let collapsed = Arc::make_mut(&mut self.collapsed_groups);
+        match collapsed.iter().position(|x| x == key) {
+            Some(i) => {
+                collapsed.remove(i);
+            }
+            None => collapsed.push(key.to_string()),
+        }
+        let collapsed = collapsed.clone();
+        AppConfig::update(|config| config.collapsed_groups = collapsed);
+    }
+
+    pub fn device(&self, device_type: &AudioDeviceType, name: &str) -> Option<&AudioDeviceState> {
+        match device_type {
+            AudioDeviceType::Source => self.sources.iter(),
+            AudioDeviceType::Sink => self.sinks.iter(),
+        }
+        .find(|x| x.name == name)
+    }
+
+    fn devices_mut(
+        &mut self,
+        device_type: &AudioDeviceType,
+    ) -> impl Iterator<Item = &mut AudioDeviceState> {
+        match device_type {
+            AudioDeviceType::Source => Arc::make_mut(&mut self.sources).iter_mut(),
+            AudioDeviceType::Sink => Arc::make_mut(&mut self.sinks).iter_mut(),
         }
     }
 
@@ -150,12 +2228,96 @@ impl AppState {
     pub fn restart(&mut self) {
         self.pulsewrapper.borrow_mut().disconnect();
         let _ = Command::new("pulseaudio").arg("-k").status();
+        let event_sink = self.event_sink.take();
         *self = Self::new();
+        self.event_sink = event_sink;
     }
 
     pub fn save_config(&mut self) {
-        let config = AppConfig {
+        let config = self.current_config();
+        self.config = Arc::new(config.clone());
+        self.unsaved = false;
+        // Hide rules and icons may have changed
+        self.apply_rules();
+        for devices in [&mut self.sources, &mut self.sinks] {
+            for device in Arc::make_mut(devices) {
+                device.update_icon();
+            }
+        }
+        confy::store("audio-select", None, config).expect("FAIL");
+    }
+
+    // Goes back to the saved settings, labels and flags of devices included.
+    pub fn revert_config(&mut self) {
+        let config = self.config.clone();
+        self.load_settings(&config);
+        self.load(&config);
+        self.unsaved = false;
+    }
+
+    // Puts every setting back to its default. Labels and flags of devices are
+    // kept, and nothing is saved until the settings are applied.
+    pub fn reset_config(&mut self) {
+        self.load_settings(&AppConfig::default());
+        self.apply_rules();
+        self.unsaved = true;
+    }
+
+    // The settings edited in the settings window, devices aside.
+    fn load_settings(&mut self, config: &AppConfig) {
+        self.use_dark_theme = config.use_dark_theme;
+        self.font = config.font.clone();
+        self.text_size = config.text_size;
+        self.hide_monitors = config.hide_monitors;
+        self.sort_mode = config.sort_mode;
+        self.group_by = config.group_by;
+        self.close_on_leave = config.close_on_leave;
+        self.close_on_focus_loss = config.close_on_focus_loss;
+        self.close_delay = config.close_delay;
+        self.placement = config.placement.clone();
+        self.move_streams_on_switch = config.move_streams_on_switch;
+        self.volume_step = config.volume_step;
+        self.notify_on_select = config.notify_on_select;
+        self.notify_on_automatic = config.notify_on_automatic;
+        self.notify_on_fallback = config.notify_on_fallback;
+        self.notify_on_lock = config.notify_on_lock;
+        self.preferred_sources = config.preferred_sources.join("\n");
+        self.preferred_sinks = config.preferred_sinks.join("\n");
+        self.fallback_sources = config.fallback_sources.join("\n");
+        self.fallback_sinks = config.fallback_sinks.join("\n");
+        self.hidden_names = name_patterns(&config.hide_rules);
+        self.shown_names = name_patterns(&config.show_rules);
+    }
+
+    fn current_config(&self) -> AppConfig {
+        AppConfig {
             use_dark_theme: self.use_dark_theme,
+            font: self.font.clone(),
+            text_size: self.text_size,
+            hide_monitors: self.hide_monitors,
+            sort_mode: self.sort_mode,
+            group_by: self.group_by,
+            collapsed_groups: self.collapsed_groups.to_vec(),
+            locked_source: self.locked_source.clone(),
+            locked_sink: self.locked_sink.clone(),
+            lock_paused_until: self.lock_paused_until,
+            notify_on_select: self.notify_on_select,
+            notify_on_automatic: self.notify_on_automatic,
+            notify_on_fallback: self.notify_on_fallback,
+            notify_on_lock: self.notify_on_lock,
+            close_on_leave: self.close_on_leave,
+            close_on_focus_loss: self.close_on_focus_loss,
+            close_delay: self.close_delay,
+            pinned: self.pinned,
+            placement: self.placement.clone(),
+            move_streams_on_switch: self.move_streams_on_switch,
+            volume_step: self.volume_step,
+            preferred_sources: lines(&self.preferred_sources),
+            preferred_sinks: lines(&self.preferred_sinks),
+            fallback_sources: lines(&self.fallback_sources),
+            fallback_sinks: lines(&self.fallback_sinks),
+            hide_rules: with_name_patterns(&self.config.hide_rules, &self.hidden_names),
+            show_rules: with_name_patterns(&self.config.show_rules, &self.shown_names),
             sources: self
                 .sources
                 .iter()
@@ -166,18 +2328,361 @@ impl AppState {
                 .iter()
                 .map(|x| x.convert_to_config())
                 .collect::<Vec<_>>(),
-        };
-        confy::store("audio-select", None, config).expect("FAIL");
+            ..(*self.config).clone()
+        }
     }
 }
 
 impl AudioDeviceState {
+    pub fn is_visible(&self) -> bool {
+        self.connected && !self.hidden && !self.rule_hidden
+    }
+
+    // Whether a reference from the config, a name, label or glob over names,
+    // refers to this device.
+    pub fn is_called(&self, reference: &str) -> bool {
+        self.name == reference
+            || self.display_label().eq_ignore_ascii_case(reference)
+            || glob_match(reference, &self.name)
+    }
+
+    // Looks up the image for the configured icon again after it was edited.
+    fn update_icon(&mut self) {
+        self.icon_path = device_icon(&self.icon, &self.name, &self.properties, &self.device_type);
+    }
+
+    pub fn display_label(&self) -> &str {
+        if self.label.is_empty() {
+            &self.default_label
+        } else {
+            &self.label
+        }
+    }
+
     fn convert_to_config(&self) -> AudioDeviceConfig {
         AudioDeviceConfig {
             name: self.name.clone(),
             label: self.label.clone(),
             hidden: self.hidden,
+            favourite: self.favourite,
+            use_count: self.use_count,
+            pair: self.pair.clone(),
+            unpaired: self.unpaired,
+            on_select: self.on_select.clone(),
+            volume_on_select: None,
+            headphones: self.headphones,
+            icon: self.icon.clone(),
+            identity: self.identity.clone(),
+        }
+    }
+}
+
+fn command_failure(command: &str, status: io::Result<ExitStatus>) -> Option<String> {
+    match status {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!("{} failed with {}", command, status)),
+        Err(e) => Some(format!("Could not run {}: {}", command, e)),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+// Entries of a settings list, one per line.
+fn lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn is_name_rule(rule: &DeviceRule) -> bool {
+    rule.name.is_some() && rule.description.is_none() && rule.properties.is_empty()
+}
+
+// The patterns of rules that only match names, which the settings can edit.
+fn name_patterns(rules: &[DeviceRule]) -> String {
+    rules
+        .iter()
+        .filter(|x| is_name_rule(x))
+        .filter_map(|x| x.name.as_ref().map(|x| x.as_str().to_string()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Replaces the name rules with one rule per line, keeping the other rules.
+fn with_name_patterns(rules: &[DeviceRule], text: &str) -> Vec<DeviceRule> {
+    rules
+        .iter()
+        .filter(|x| !is_name_rule(x))
+        .cloned()
+        .chain(lines(text).into_iter().map(|x| DeviceRule {
+            name: Some(Pattern::from(x)),
+            ..DeviceRule::default()
+        }))
+        .collect()
+}
+
+// Finds a connected device by pulseaudio name, then by label, then by treating
+// the reference as a glob over names. Globs only match monitors when they say
+// so, e.g. "*.monitor".
+fn resolve<'a>(
+    devices: impl Iterator<Item = &'a AudioDeviceState> + Clone,
+    reference: &str,
+) -> Option<(AudioDeviceType, String)> {
+    let mut devices = devices.filter(|x| x.connected);
+    devices
+        .clone()
+        .find(|x| x.name == reference)
+        .or_else(|| {
+            devices
+                .clone()
+                .find(|x| x.display_label().eq_ignore_ascii_case(reference))
+        })
+        .or_else(|| {
+            devices.find(|x| {
+                glob_match(reference, &x.name)
+                    && (!is_monitor(&x.properties) || reference.contains("monitor"))
+            })
+        })
+        .map(|x| (x.device_type.clone(), x.name.clone()))
+}
+
+// The properties of a device with the active port and owning card added as
+// `port.name`, `port.description`, `card.name` and `card.description`, so label
+// templates can use them.
+fn template_properties(device: &DeviceInfo, cards: &[Card]) -> BTreeMap<String, String> {
+    let mut properties = device.properties.clone();
+    let port = device
+        .ports
+        .iter()
+        .find(|x| device.active_port.as_ref() == Some(&x.name));
+    if let Some(port) = port {
+        properties.insert(String::from("port.name"), port.name.clone());
+        properties.insert(String::from("port.description"), port.description.clone());
+    }
+    if let Some(card) = cards.iter().find(|x| Some(x.index) == device.card) {
+        properties.insert(String::from("card.name"), card.name.clone());
+        properties.insert(String::from("card.description"), card.description.clone());
+    }
+    properties
+}
+
+// Pairs saved entries with the devices pulseaudio reports, first by exact name
+// and then by identity. Entries matched by identity take over the device's
+// current name, and entries saved before identities existed pick one up here.
+fn load_devices(
+    config: &AppConfig,
+    mut devices: Vec<DeviceInfo>,
+    device_type: AudioDeviceType,
+    cards: &[Card],
+    pulsewrapper: &Arc<RefCell<PulseWrapper>>,
+) -> Vec<AudioDeviceState> {
+    let configs = match device_type {
+        AudioDeviceType::Source => &config.sources,
+        AudioDeviceType::Sink => &config.sinks,
+    };
+    let default_label = |device: &DeviceInfo| {
+        automatic_label(
+            &config.label_templates,
+            &config.label_substitutions,
+            &device.name,
+            &device.description,
+            &template_properties(device, cards),
+        )
+    };
+    let card = |device: &DeviceInfo| {
+        cards
+            .iter()
+            .find(|x| Some(x.index) == device.card)
+            .map_or_else(String::new, |x| x.description.clone())
+    };
+    let mut result = Vec::new();
+
+    // Every exact name is claimed before any identity, as devices of one card
+    // share their serial and bus path and an entry could take another's device.
+    let mut matched = configs
+        .iter()
+        .map(|entry| {
+            let i = devices.iter().position(|x| x.name == entry.name)?;
+            Some(devices.remove(i))
+        })
+        .collect::<Vec<_>>();
+    for (entry, device) in configs.iter().zip(matched.iter_mut()) {
+        if device.is_none() {
+            if let Some(i) = devices.iter().position(|x| entry.identity.matches(x)) {
+                *device = Some(devices.remove(i));
+            }
+        }
+    }
+
+    for (entry, device) in configs.iter().zip(matched) {
+        let identity = match &device {
+            Some(device) if entry.identity.is_empty() => {
+                DeviceIdentity::from_properties(&device.properties)
+            }
+            _ => entry.identity.clone(),
+        };
+        let (label, default_label) = match &device {
+            // Older configs stored the description as the label of every
+            // device they saw, which isn't a label the user chose.
+            Some(device) if device.description == entry.label => {
+                (String::new(), default_label(device))
+            }
+            Some(device) => (entry.label.clone(), default_label(device)),
+            None => (entry.label.clone(), entry.name.clone()),
+        };
+        result.push(AudioDeviceState {
+            name: device
+                .as_ref()
+                .map_or_else(|| entry.name.clone(), |x| x.name.clone()),
+            label,
+            default_label,
+            device_type: device_type.clone(),
+            connected: device.is_some(),
+            hidden: entry.hidden,
+            rule_hidden: false,
+            favourite: entry.favourite,
+            use_count: entry.use_count,
+            index: device.as_ref().map_or(0, |x| x.index),
+            card: device.as_ref().map_or_else(String::new, card),
+            pair: entry.pair.clone(),
+            partner: String::new(),
+            unpaired: entry.unpaired,
+            volume: device.as_ref().map_or(0, |x| x.volume),
+            muted: device.as_ref().is_some_and(|x| x.mute),
+            on_select: SelectActions {
+                volume: entry.on_select.volume.or(entry.volume_on_select),
+                ..entry.on_select.clone()
+            },
+            headphones: entry.headphones,
+            icon: entry.icon.clone(),
+            icon_path: device_icon(
+                &entry.icon,
+                &entry.name,
+                device.as_ref().map_or(&BTreeMap::new(), |x| &x.properties),
+                &device_type,
+            ),
+            running: device.as_ref().is_some_and(|x| x.running),
+            suspended: device.as_ref().is_some_and(|x| x.suspended),
+            apps: 0,
+            focused: false,
+            renaming: false,
+            identity,
+            properties: Arc::new(device.map(|x| x.properties).unwrap_or_default()),
+            pulsewrapper: pulsewrapper.clone(),
+        });
+    }
+
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    for device in devices {
+        result.push(AudioDeviceState {
+            identity: DeviceIdentity::from_properties(&device.properties),
+            default_label: default_label(&device),
+            card: card(&device),
+            pair: None,
+            partner: String::new(),
+            unpaired: false,
+            volume: device.volume,
+            muted: device.mute,
+            on_select: SelectActions::default(),
+            headphones: false,
+            icon: String::new(),
+            icon_path: device_icon("", &device.name, &device.properties, &device_type),
+            running: device.running,
+            suspended: device.suspended,
+            apps: 0,
+            focused: false,
+            renaming: false,
+            name: device.name,
+            label: String::new(),
+            device_type: device_type.clone(),
+            connected: true,
+            hidden: false,
+            rule_hidden: false,
+            favourite: false,
+            use_count: 0,
+            index: device.index,
+            properties: Arc::new(device.properties),
+            pulsewrapper: pulsewrapper.clone(),
+        });
+    }
+
+    result
+}
+
+impl AppConfig {
+    // Applies a change to the config on disk without touching anything else
+    // that was loaded or edited since.
+    fn update(f: impl FnOnce(&mut AppConfig)) {
+        match confy::load::<AppConfig>("audio-select", None) {
+            Ok(mut config) => {
+                f(&mut config);
+                if let Err(e) = confy::store("audio-select", None, config) {
+                    eprintln!("Failed to store config: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Failed to load config: {}", e),
+        }
+    }
+
+    // The saved entry of a device, found like when devices are loaded, first
+    // by name and then by identity, in which case it takes over the device's
+    // current name. It's added at the end of its list if the device was never
+    // saved.
+    fn entry_mut(&mut self, device: &AudioDeviceState) -> &mut AudioDeviceConfig {
+        let devices = match device.device_type {
+            AudioDeviceType::Source => &mut self.sources,
+            AudioDeviceType::Sink => &mut self.sinks,
+        };
+        let position = devices
+            .iter()
+            .position(|x| x.name == device.name)
+            .or_else(|| {
+                let i = devices
+                    .iter()
+                    .position(|x| x.identity.matches_device(&device.name, &device.properties))?;
+                devices[i].name = device.name.clone();
+                Some(i)
+            });
+        let i = match position {
+            Some(i) => i,
+            None => {
+                devices.push(AudioDeviceConfig {
+                    name: device.name.clone(),
+                    label: String::new(),
+                    hidden: false,
+                    favourite: false,
+                    use_count: 0,
+                    pair: None,
+                    unpaired: false,
+                    headphones: false,
+                    icon: String::new(),
+                    identity: device.identity.clone(),
+                    on_select: SelectActions::default(),
+                    volume_on_select: None,
+                });
+                devices.len() - 1
+            }
+        };
+        &mut devices[i]
+    }
+
+    fn hides(
+        &self,
+        name: &str,
+        properties: &BTreeMap<String, String>,
+        hide_monitors: bool,
+    ) -> bool {
+        if self.show_rules.iter().any(|x| x.matches(name, properties)) {
+            return false;
         }
+        (hide_monitors && is_monitor(properties))
+            || self.hide_rules.iter().any(|x| x.matches(name, properties))
     }
 }
 
@@ -186,8 +2691,136 @@ impl Default for AppConfig {
         AppConfig {
             // default_source_name: Some(String::from("test")),
             use_dark_theme: true,
+            font: String::new(),
+            text_size: 15.0,
+            hide_monitors: false,
+            sort_mode: SortMode::Manual,
+            group_by: GroupBy::Nothing,
+            collapsed_groups: Vec::new(),
+            label_templates: Vec::new(),
+            preferred_sources: Vec::new(),
+            preferred_sinks: Vec::new(),
+            move_streams_on_switch: false,
+            fallback_sources: Vec::new(),
+            fallback_sinks: Vec::new(),
+            locked_source: None,
+            locked_sink: None,
+            lock_override_minutes: 5,
+            lock_paused_until: None,
+            restore_volume_on_select: false,
+            headphone_volume_cap: None,
+            notify_on_select: false,
+            notify_on_automatic: true,
+            notify_on_fallback: true,
+            notify_on_lock: true,
+            volume_step: 5,
+            close_on_leave: true,
+            close_on_focus_loss: false,
+            close_delay: 300,
+            pinned: false,
+            min_window_size: (300.0, 200.0),
+            max_window_size: (600.0, 800.0),
+            keys: KeyBindings::default(),
+            placement: Placement::default(),
             sources: Vec::new(),
             sinks: Vec::new(),
+            hide_rules: Vec::new(),
+            show_rules: Vec::new(),
+            label_substitutions: Vec::new(),
+            groups: Vec::new(),
+            scenes: Vec::new(),
+            app_rules: Vec::new(),
         }
     }
 }
+
+// The volume of the default device, for the sliders of the pinned mixer.
+pub struct DefaultVolume(pub AudioDeviceType);
+
+impl Lens<AppState, f64> for DefaultVolume {
+    fn with<V, F: FnOnce(&f64) -> V>(&self, data: &AppState, f: F) -> V {
+        let volume = data
+            .device(&self.0, data.default_of(&self.0))
+            .map_or(0, |x| x.volume);
+        f(&(volume as f64))
+    }
+
+    fn with_mut<V, F: FnOnce(&mut f64) -> V>(&self, data: &mut AppState, f: F) -> V {
+        let name = data.default_of(&self.0).to_string();
+        let volume = data.device(&self.0, &name).map_or(0, |x| x.volume);
+        let mut value = volume as f64;
+        let result = f(&mut value);
+        if value.round() as u32 != volume {
+            data.status = data
+                .set_volume(&self.0, &name, value.round() as u32)
+                .join("\n");
+        }
+        result
+    }
+}
+
+// Presents one device list as the popup shows it. Changes made to devices
+// through the lens are written back to the matching device by name.
+pub struct GroupedDevices(pub AudioDeviceType);
+
+impl Lens<AppState, Arc<Vec<DeviceGroup>>> for GroupedDevices {
+    fn with<V, F: FnOnce(&Arc<Vec<DeviceGroup>>) -> V>(&self, data: &AppState, f: F) -> V {
+        f(&Arc::new(data.grouped_devices(&self.0)))
+    }
+
+    fn with_mut<V, F: FnOnce(&mut Arc<Vec<DeviceGroup>>) -> V>(
+        &self,
+        data: &mut AppState,
+        f: F,
+    ) -> V {
+        let mut groups = Arc::new(data.grouped_devices(&self.0));
+        let result = f(&mut groups);
+        for device in groups.iter().flat_map(|x| x.devices.iter()) {
+            if data
+                .device(&self.0, &device.name)
+                .is_some_and(|x| !x.same(device))
+            {
+                if let Some(x) = data.devices_mut(&self.0).find(|x| x.name == device.name) {
+                    *x = device.clone();
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(config: &AppConfig) {
+        let text = toml::to_string(config).expect("config should serialize");
+        let loaded: AppConfig = toml::from_str(&text).expect("config should load");
+        assert_eq!(toml::to_string(&loaded).unwrap(), text);
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        round_trip(&AppConfig::default());
+    }
+
+    #[test]
+    fn config_with_one_empty_device_list_round_trips() {
+        let mut config = AppConfig::default();
+        config.sources.push(AudioDeviceConfig {
+            name: String::from("alsa_input.usb-Blue_Yeti-00.analog-stereo"),
+            label: String::from("Yeti"),
+            hidden: false,
+            favourite: true,
+            use_count: 3,
+            pair: None,
+            unpaired: false,
+            headphones: false,
+            icon: String::new(),
+            identity: DeviceIdentity::default(),
+            on_select: SelectActions::default(),
+            volume_on_select: None,
+        });
+        round_trip(&config);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::data::AudioDeviceType;
+use crate::matching::{FORM_FACTOR_PROPERTY, ICON_NAME_PROPERTY};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const ICON_SIZE: u32 = 24;
+const EXTENSIONS: [&str; 2] = ["png", "svg"];
+const FALLBACK_THEME: &str = "hicolor";
+
+// Lookups by icon name, as a theme has to be searched through for each.
+static CACHE: Mutex<BTreeMap<String, Option<PathBuf>>> = Mutex::new(BTreeMap::new());
+
+// The image file for a device's icon, or an empty string if none is found. The
+// override is an icon name or a path to an image.
+pub fn device_icon(
+    icon: &str,
+    name: &str,
+    properties: &BTreeMap<String, String>,
+    device_type: &AudioDeviceType,
+) -> String {
+    for candidate in candidates(icon, name, properties, device_type) {
+        let path = if candidate.contains('/') {
+            Some(PathBuf::from(&candidate)).filter(|x| x.is_file())
+        } else {
+            lookup(&candidate)
+        };
+        if let Some(path) = path {
+            return path.to_string_lossy().into_owned();
+        }
+    }
+    String::new()
+}
+
+// Icon names to try, most specific first.
+fn candidates(
+    icon: &str,
+    name: &str,
+    properties: &BTreeMap<String, String>,
+    device_type: &AudioDeviceType,
+) -> Vec<String> {
+    let mut names = Vec::new();
+    if !icon.is_empty() {
+        names.push(icon.to_string());
+    }
+    if let Some(mut icon_name) = properties.get(ICON_NAME_PROPERTY).map(String::as_str) {
+        names.push(icon_name.to_string());
+        // "audio-headset-bluetooth" falls back to "audio-headset"
+        while let Some(i) = icon_name
+            .rfind('-')
+            .filter(|&i| icon_name[..i].contains('-'))
+        {
+            icon_name = &icon_name[..i];
+            names.push(icon_name.to_string());
+        }
+    }
+    let form_factor = match properties.get(FORM_FACTOR_PROPERTY).map(String::as_str) {
+        Some("headset" | "hands-free") => Some("audio-headset"),
+        Some("headphone") => Some("audio-headphones"),
+        Some("speaker") => Some("audio-speakers"),
+        Some("microphone") => Some("audio-input-microphone"),
+        Some("webcam") => Some("camera-web"),
+        Some("tv") => Some("video-display"),
+        _ => None,
+    };
+    names.extend(form_factor.map(String::from));
+    if name.to_lowercase().contains("hdmi") {
+        names.push(String::from("video-display"));
+    }
+    let fallback = match device_type {
+        AudioDeviceType::Source => "audio-input-microphone",
+        AudioDeviceType::Sink => "audio-speakers",
+    };
+    names.push(String::from(fallback));
+    names.push(String::from("audio-card"));
+    names
+}
+
+fn lookup(name: &str) -> Option<PathBuf> {
+    let mut cache = CACHE.lock().unwrap();
+    cache
+        .entry(name.to_string())
+        .or_insert_with(|| find(name))
+        .clone()
+}
+
+// Follows the freedesktop icon theme spec: the user's theme, the themes it
+// inherits from, hicolor and then the unthemed pixmaps.
+fn find(name: &str) -> Option<PathBuf> {
+    let bases = base_dirs();
+    let mut queue = vec![current_theme()];
+    let mut seen = Vec::new();
+    while !queue.is_empty() {
+        let theme = queue.remove(0);
+        if seen.contains(&theme) {
+            continue;
+        }
+        for base in &bases {
+            let dir = base.join(&theme);
+            let index = match fs::read_to_string(dir.join("index.theme")) {
+                Ok(index) => parse_index(&index),
+                Err(_) => continue,
+            };
+            for icon_name in [name.to_string(), format!("{}-symbolic", name)] {
+                if let Some(path) = find_in_theme(&dir, &index, &icon_name) {
+                    return Some(path);
+                }
+            }
+            if let Some(inherits) = index.get("Icon Theme").and_then(|x| x.get("Inherits")) {
+                queue.extend(inherits.split(',').map(|x| x.trim().to_string()));
+            }
+        }
+        seen.push(theme);
+        if queue.is_empty() && !seen.iter().any(|x| x == FALLBACK_THEME) {
+            queue.push(String::from(FALLBACK_THEME));
+        }
+    }
+    EXTENSIONS
+        .iter()
+        .map(|x| Path::new("/usr/share/pixmaps").join(format!("{}.{}", name, x)))
+        .find(|x| x.is_file())
+}
+
+// Searches the directories of a theme, those closest to the icon size first.
+fn find_in_theme(
+    dir: &Path,
+    index: &BTreeMap<String, BTreeMap<String, String>>,
+    name: &str,
+) -> Option<PathBuf> {
+    let directories = index.get("Icon Theme")?.get("Directories")?;
+    let mut directories = directories
+        .split(',')
+        .map(str::trim)
+        .filter_map(|x| Some((size_distance(index.get(x)?)?, x)))
+        .collect::<Vec<_>>();
+    directories.sort_by_key(|x| x.0);
+    directories.iter().find_map(|(_, subdir)| {
+        EXTENSIONS
+            .iter()
+            .map(|x| dir.join(subdir).join(format!("{}.{}", name, x)))
+            .find(|x| x.is_file())
+    })
+}
+
+// How far the icons of a theme directory are from the wanted size, None for
+// directories meant for high DPI screens.
+fn size_distance(section: &BTreeMap<String, String>) -> Option<u32> {
+    let number = |key: &str| section.get(key).and_then(|x| x.parse::<u32>().ok());
+    if number("Scale").unwrap_or(1) != 1 {
+        return None;
+    }
+    let size = number("Size")?;
+    let (min, max) = match section.get("Type").map(String::as_str) {
+        Some("Scalable") => (
+            number("MinSize").unwrap_or(size),
+            number("MaxSize").unwrap_or(size),
+        ),
+        Some("Fixed") => (size, size),
+        _ => {
+            let threshold = number("Threshold").unwrap_or(2);
+            (size.saturating_sub(threshold), size + threshold)
+        }
+    };
+    Some(min.saturating_sub(ICON_SIZE) + ICON_SIZE.saturating_sub(max))
+}
+
+fn parse_index(index: &str) -> BTreeMap<String, BTreeMap<String, String>> {
+    let mut sections: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut section = String::new();
+    for line in index.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            section = name.to_string();
+        } else if let Some((key, value)) = line.split_once('=') {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    sections
+}
+
+fn base_dirs() -> Vec<PathBuf> {
+    let home = env::var("HOME").map(PathBuf::from).unwrap_or_default();
+    let data_home = env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home.join(".local/share"));
+    let data_dirs =
+        env::var("XDG_DATA_DIRS").unwrap_or_else(|_| String::from("/usr/local/share:/usr/share"));
+    let mut dirs = vec![home.join(".icons"), data_home.join("icons")];
+    dirs.extend(
+        data_dirs
+            .split(':')
+            .filter(|x| !x.is_empty())
+            .map(|x| Path::new(x).join("icons")),
+    );
+    dirs
+}
+
+// The icon theme picked in the GTK settings, or the GTK default.
+fn current_theme() -> String {
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            env::var("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_default()
+                .join(".config")
+        });
+    fs::read_to_string(config_home.join("gtk-3.0/settings.ini"))
+        .ok()
+        .and_then(|settings| {
+            settings.lines().find_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                (key.trim() == "gtk-icon-theme-name")
+                    .then(|| value.trim().trim_matches('"').to_string())
+            })
+        })
+        .unwrap_or_else(|| String::from("Adwaita"))
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyAction {
+    Next,
+    Previous,
+    Select,
+    Mute,
+    VolumeUp,
+    VolumeDown,
+    NextSection,
+    Close,
+}
+
+// Keys are named like `KeyboardEvent.key` in browsers, e.g. "ArrowDown",
+// "Enter" or "j". The number keys always pick the Nth output, with Shift the
+// Nth input.
+#[derive(Serialize, Clone, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub next: Vec<String>,
+    pub previous: Vec<String>,
+    pub select: Vec<String>,
+    pub mute: Vec<String>,
+    pub volume_up: Vec<String>,
+    pub volume_down: Vec<String>,
+    pub next_section: Vec<String>,
+    pub close: Vec<String>,
+}
+
+impl KeyBindings {
+    pub fn action_for(&self, key: &str) -> Option<KeyAction> {
+        [
+            (&self.next, KeyAction::Next),
+            (&self.previous, KeyAction::Previous),
+            (&self.select, KeyAction::Select),
+            (&self.mute, KeyAction::Mute),
+            (&self.volume_up, KeyAction::VolumeUp),
+            (&self.volume_down, KeyAction::VolumeDown),
+            (&self.next_section, KeyAction::NextSection),
+            (&self.close, KeyAction::Close),
+        ]
+        .into_iter()
+        .find(|(keys, _)| keys.iter().any(|x| x == key))
+        .map(|(_, action)| action)
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let keys = |keys: &[&str]| keys.iter().map(|x| x.to_string()).collect();
+        KeyBindings {
+            next: keys(&["ArrowDown", "j"]),
+            previous: keys(&["ArrowUp", "k"]),
+            select: keys(&["Enter"]),
+            mute: keys(&["m"]),
+            volume_up: keys(&["+", "="]),
+            volume_down: keys(&["-"]),
+            next_section: keys(&["Tab"]),
+            close: keys(&["Escape"]),
+        }
+    }
+}
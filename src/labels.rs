@@ -0,0 +1,103 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Clone, Deserialize, Debug, PartialEq)]
+pub struct LabelSubstitution {
+    pub pattern: SubstitutionPattern,
+    #[serde(default)]
+    pub replacement: String,
+}
+
+// A regex compiled once when the config is read, which reports it if invalid.
+// An invalid one replaces nothing.
+#[derive(Serialize, Clone, Deserialize, Debug)]
+#[serde(from = "String", into = "String")]
+pub struct SubstitutionPattern {
+    text: String,
+    regex: Option<Regex>,
+}
+
+impl From<String> for SubstitutionPattern {
+    fn from(text: String) -> Self {
+        let regex = match Regex::new(&text) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                eprintln!("Invalid regex {}: {}", text, e);
+                None
+            }
+        };
+        SubstitutionPattern { text, regex }
+    }
+}
+
+impl From<SubstitutionPattern> for String {
+    fn from(pattern: SubstitutionPattern) -> Self {
+        pattern.text
+    }
+}
+
+impl PartialEq for SubstitutionPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+// Builds the label for a device the user hasn't labelled. The first template
+// whose placeholders all resolve wins, otherwise the description is used.
+pub fn automatic_label(
+    templates: &[String],
+    substitutions: &[LabelSubstitution],
+    name: &str,
+    description: &str,
+    properties: &BTreeMap<String, String>,
+) -> String {
+    let label = templates
+        .iter()
+        .find_map(|x| fill_template(x, name, properties))
+        .unwrap_or_else(|| description.to_string());
+    let substituted = substitute(substitutions, &label);
+    if substituted.is_empty() {
+        label
+    } else {
+        substituted
+    }
+}
+
+// Replaces `{property.key}` placeholders with device properties, including
+// the port and card keys added by the caller, and `{name}` with the pulseaudio
+// name. Returns None if any placeholder has no value.
+fn fill_template(
+    template: &str,
+    name: &str,
+    properties: &BTreeMap<String, String>,
+) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}')?;
+        let key = &rest[start + 1..end];
+        let value = match key {
+            "name" => Some(name),
+            _ => properties.get(key).map(String::as_str),
+        }
+        .filter(|x| !x.trim().is_empty())?;
+        result.push_str(&rest[..start]);
+        result.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+fn substitute(substitutions: &[LabelSubstitution], label: &str) -> String {
+    let mut result = label.to_string();
+    for substitution in substitutions {
+        if let Some(regex) = &substitution.pattern.regex {
+            result = regex
+                .replace_all(&result, substitution.replacement.as_str())
+                .into_owned();
+        }
+    }
+    result.trim().to_string()
+}
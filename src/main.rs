@@ -1,62 +1,83 @@
 use druid::widget::Controller;
-use druid::{AppLauncher, Screen, WindowDesc};
-use druid::{Env, Event, EventCtx, InternalEvent, Widget, WidgetExt};
-use mouse_position::mouse_position::Mouse;
+use druid::{AppLauncher, TimerToken, WindowDesc};
+use druid::{Env, Event, EventCtx, InternalEvent, UpdateCtx, Widget, WidgetExt};
+use gtk::prelude::*;
+use std::time::Duration;
 
+mod cli;
 mod data;
+mod icons;
+mod keys;
+mod labels;
+mod matching;
+mod notifications;
+mod placement;
 mod pulsewrapper;
 mod theme;
 mod ui;
+mod volumes;
+mod watcher;
 
 use data::AppState;
-
-pub const MAIN_WINDOW_WIDTH: f64 = 300.0;
-pub const MAIN_WINDOW_HEIGHT: f64 = 400.0;
+use placement::PlacementMode;
 
 fn main() {
-    let state = AppState::new();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let options = match cli::Command::parse(&args) {
+        Ok(cli::Command::Popup(options)) => options,
+        Ok(command) => std::process::exit(cli::run(command)),
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let main_window = WindowDesc::new(ui::build_ui().controller(WindowController))
-        .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT))
+    let mut state = AppState::new();
+    let size = ui::window_size(&state);
+    let placement = options.apply(state.placement.clone());
+    let controller = WindowController {
+        remember: placement.mode == PlacementMode::Remember,
+        close_timer: TimerToken::INVALID,
+        focus_timer: TimerToken::INVALID,
+        was_active: false,
+    };
+
+    let main_window = WindowDesc::new(ui::build_ui().controller(controller))
+        .window_size(size)
         .resizable(false)
-        .set_position(get_position())
+        .set_always_on_top(state.pinned)
+        .set_position(placement::get_position(size, &placement))
         .title("Audio Select");
 
-    AppLauncher::with_window(main_window)
+    let launcher = AppLauncher::with_window(main_window);
+    state.set_event_sink(launcher.get_external_handle());
+    if state.ready {
+        watcher::spawn(launcher.get_external_handle());
+    }
+    launcher
         .configure_env(theme::setup)
         .launch(state)
         .expect("Failed to launch application");
 }
 
-pub fn get_position() -> (f64, f64) {
-    let druid::Rect { x0, x1, y0, y1 } = Screen::get_display_rect();
-    let middle_x = (x0 + x1) / 2.0;
-    let middle_y = (y0 + y1) / 2.0;
-    // match Mouse::get_mouse_position() {
-    //     Mouse::Position { x, y } => { println!("{}, {}", x, y); },
-    //     Mouse::Error => (),
-    // };
-    match Mouse::get_mouse_position() {
-        Mouse::Position { x, y } => (
-            num::clamp(
-                (x as f64) - MAIN_WINDOW_WIDTH / 2.0,
-                x0 + 5.0,
-                x1 - MAIN_WINDOW_WIDTH - 5.0,
-            ),
-            if (y as f64) < middle_y {
-                (y as f64) + 10.0
-            } else {
-                (y as f64) - MAIN_WINDOW_HEIGHT - 45.0
-            },
-        ),
-        Mouse::Error => (
-            middle_x - (MAIN_WINDOW_WIDTH / 2.0),
-            middle_y - (MAIN_WINDOW_HEIGHT / 2.0),
-        ),
-    }
+// How often the popup checks whether it still has the focus
+const FOCUS_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+struct WindowController {
+    remember: bool,
+    close_timer: TimerToken, // pending close after the pointer left
+    focus_timer: TimerToken,
+    was_active: bool,
 }
 
-struct WindowController;
+impl WindowController {
+    fn close(&self, ctx: &mut EventCtx) {
+        if self.remember {
+            placement::remember(ctx.window().get_position());
+        }
+        ctx.window().close();
+    }
+}
 
 impl<W: Widget<AppState>> Controller<AppState, W> for WindowController {
     fn event(
@@ -67,11 +88,87 @@ impl<W: Widget<AppState>> Controller<AppState, W> for WindowController {
         data: &mut AppState,
         env: &Env,
     ) {
-        if let Event::Internal(InternalEvent::MouseLeave) = event {
-            if data.close_on_leave {
-                ctx.window().close();
+        let may_close = !data.pinned;
+        match event {
+            Event::WindowConnected => {
+                self.focus_timer = ctx.request_timer(FOCUS_CHECK_INTERVAL);
+            }
+            Event::Internal(InternalEvent::MouseLeave) if data.close_on_leave && may_close => {
+                self.close_timer = ctx.request_timer(Duration::from_millis(data.close_delay));
+            }
+            // The pointer came back in time
+            Event::MouseMove(_) => self.close_timer = TimerToken::INVALID,
+            Event::Timer(token)
+                if *token == self.close_timer && data.close_on_leave && may_close =>
+            {
+                // A menu or the settings take the pointer out of the popup,
+                // which closes once they're gone unless the pointer came back
+                if other_window_open() {
+                    self.close_timer = ctx.request_timer(Duration::from_millis(data.close_delay));
+                } else {
+                    self.close(ctx);
+                }
+            }
+            Event::Timer(token) if *token == self.focus_timer => {
+                // The popup may not get the focus right away, so it only
+                // closes after having had it
+                if app_is_active() {
+                    self.was_active = true;
+                } else if self.was_active
+                    && data.close_on_focus_loss
+                    && may_close
+                    && !other_window_open()
+                {
+                    self.close(ctx);
+                    return;
+                }
+                self.focus_timer = ctx.request_timer(FOCUS_CHECK_INTERVAL);
+            }
+            Event::Command(cmd) if cmd.is(ui::CLOSE_POPUP) => {
+                self.close(ctx);
+                return;
             }
+            Event::WindowCloseRequested if self.remember => {
+                placement::remember(ctx.window().get_position());
+            }
+            _ => (),
         }
         child.event(ctx, event, data, env)
     }
+
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &AppState,
+        data: &AppState,
+        env: &Env,
+    ) {
+        if old_data.pinned != data.pinned {
+            ctx.window().set_always_on_top(data.pinned);
+        }
+        child.update(ctx, old_data, data, env)
+    }
+}
+
+// Druid doesn't tell windows they lost the focus, so GTK is asked whether any
+// window of the app is active, which keeps the popup open while the settings
+// or a menu are used.
+fn app_is_active() -> bool {
+    gtk::Window::list_toplevels()
+        .into_iter()
+        .filter_map(|x| x.downcast::<gtk::Window>().ok())
+        .any(|x| x.is_active())
+}
+
+// Whether the settings, an inspector or a context menu are showing besides
+// the popup. Druid doesn't say when a menu is dismissed, but GTK shows it in a
+// window of its own.
+fn other_window_open() -> bool {
+    gtk::Window::list_toplevels()
+        .into_iter()
+        .filter_map(|x| x.downcast::<gtk::Window>().ok())
+        .filter(gtk::prelude::WidgetExt::is_visible)
+        .count()
+        > 1
 }
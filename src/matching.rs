@@ -0,0 +1,273 @@
+use crate::pulsewrapper::DeviceInfo;
+use druid::Data;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const SERIAL_PROPERTY: &str = "device.serial";
+const BUS_PATH_PROPERTY: &str = "device.bus_path";
+const BUS_PROPERTY: &str = "device.bus";
+const CLASS_PROPERTY: &str = "device.class";
+const BLUEZ_ADDRESS_PROPERTY: &str = "api.bluez5.address";
+const DEVICE_STRING_PROPERTY: &str = "device.string";
+pub const DESCRIPTION_PROPERTY: &str = "device.description";
+pub const APPLICATION_NAME_PROPERTY: &str = "application.name";
+pub const FORM_FACTOR_PROPERTY: &str = "device.form_factor";
+pub const ICON_NAME_PROPERTY: &str = "device.icon_name";
+const BATTERY_PROPERTY: &str = "bluetooth.battery";
+const MONITOR_CLASS: &str = "monitor";
+
+// Properties the popup search looks at besides the label and name.
+pub const SEARCH_PROPERTIES: [&str; 5] = [
+    DESCRIPTION_PROPERTY,
+    "device.product.name",
+    "device.vendor.name",
+    FORM_FACTOR_PROPERTY,
+    BUS_PROPERTY,
+];
+
+// Properties that survive card profile changes and bluetooth reconnects, unlike
+// the pulseaudio name. Any field left empty is ignored when matching.
+#[derive(Serialize, Clone, Deserialize, Debug, Default, PartialEq, Data)]
+#[serde(default)]
+pub struct DeviceIdentity {
+    pub serial: Option<String>,
+    pub bus_path: Option<String>,
+    pub bluetooth_address: Option<String>,
+    pub class: Option<String>,
+    pub name_glob: Option<String>,
+}
+
+impl DeviceIdentity {
+    pub fn from_properties(properties: &BTreeMap<String, String>) -> Self {
+        let bluetooth_address = properties.get(BLUEZ_ADDRESS_PROPERTY).or_else(|| {
+            if properties.get(BUS_PROPERTY).map(String::as_str) == Some("bluetooth") {
+                properties.get(DEVICE_STRING_PROPERTY)
+            } else {
+                None
+            }
+        });
+        DeviceIdentity {
+            serial: properties.get(SERIAL_PROPERTY).cloned(),
+            bus_path: properties.get(BUS_PATH_PROPERTY).cloned(),
+            bluetooth_address: bluetooth_address.cloned(),
+            class: properties.get(CLASS_PROPERTY).cloned(),
+            name_glob: None,
+        }
+    }
+
+    // The identifiers that are set, for the settings window.
+    pub fn describe(&self) -> String {
+        [
+            ("serial", &self.serial),
+            ("bus path", &self.bus_path),
+            ("bluetooth", &self.bluetooth_address),
+            ("class", &self.class),
+            ("name", &self.name_glob),
+        ]
+        .iter()
+        .filter_map(|(name, value)| Some(format!("{} {}", name, value.as_ref()?)))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.serial.is_none()
+            && self.bus_path.is_none()
+            && self.bluetooth_address.is_none()
+            && self.name_glob.is_none()
+    }
+
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        self.matches_device(&device.name, &device.properties)
+    }
+
+    pub fn matches_device(&self, name: &str, properties: &BTreeMap<String, String>) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        if let Some(glob) = &self.name_glob {
+            if !glob_match(glob, name) {
+                return false;
+            }
+        }
+        // Monitors share the serial and bus path of the card they belong to,
+        // so the class keeps a microphone entry from claiming a monitor.
+        if self.class.is_some() && self.class.as_ref() != properties.get(CLASS_PROPERTY) {
+            return false;
+        }
+        let other = DeviceIdentity::from_properties(properties);
+        // Prefer the most specific identifier both sides know about.
+        if let (Some(a), Some(b)) = (&self.serial, &other.serial) {
+            return a == b;
+        }
+        if let (Some(a), Some(b)) = (&self.bluetooth_address, &other.bluetooth_address) {
+            return a.eq_ignore_ascii_case(b);
+        }
+        if let (Some(a), Some(b)) = (&self.bus_path, &other.bus_path) {
+            return a == b;
+        }
+        self.name_glob.is_some()
+            && self.serial.is_none()
+            && self.bluetooth_address.is_none()
+            && self.bus_path.is_none()
+    }
+}
+
+// A shell style glob, or a regex when wrapped in slashes like `/^HDMI/`. Regexes
+// are compiled once when the config is read, which reports invalid ones.
+#[derive(Serialize, Clone, Deserialize, Debug)]
+#[serde(from = "String", into = "String")]
+pub struct Pattern {
+    text: String,
+    matcher: Matcher,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Glob,
+    Regex(Regex),
+    Invalid, // a regex that doesn't compile, matches nothing
+}
+
+impl Pattern {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        match &self.matcher {
+            Matcher::Glob => glob_match(&self.text, text),
+            Matcher::Regex(regex) => regex.is_match(text),
+            Matcher::Invalid => false,
+        }
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(text: String) -> Self {
+        let regex = text
+            .strip_prefix('/')
+            .and_then(|x| x.strip_suffix('/'))
+            .filter(|x| !x.is_empty());
+        let matcher = match regex.map(Regex::new) {
+            None => Matcher::Glob,
+            Some(Ok(regex)) => Matcher::Regex(regex),
+            Some(Err(e)) => {
+                eprintln!("Invalid regex {}: {}", text, e);
+                Matcher::Invalid
+            }
+        };
+        Pattern { text, matcher }
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> Self {
+        pattern.text
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+// Matches a device when every condition that is set matches. A rule without
+// any conditions matches nothing.
+#[derive(Serialize, Clone, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct DeviceRule {
+    pub name: Option<Pattern>,
+    pub description: Option<Pattern>,
+    pub properties: BTreeMap<String, Pattern>,
+}
+
+impl DeviceRule {
+    pub fn matches(&self, name: &str, properties: &BTreeMap<String, String>) -> bool {
+        if self.name.is_none() && self.description.is_none() && self.properties.is_empty() {
+            return false;
+        }
+        let description = properties
+            .get(DESCRIPTION_PROPERTY)
+            .map_or("", String::as_str);
+        self.name.as_ref().is_none_or(|x| x.matches(name))
+            && self
+                .description
+                .as_ref()
+                .is_none_or(|x| x.matches(description))
+            && self.properties.iter().all(|(key, pattern)| {
+                properties
+                    .get(key)
+                    .is_some_and(|value| pattern.matches(value))
+            })
+    }
+}
+
+pub fn bus_of(properties: &BTreeMap<String, String>) -> Option<&str> {
+    properties.get(BUS_PROPERTY).map(String::as_str)
+}
+
+pub fn is_monitor(properties: &BTreeMap<String, String>) -> bool {
+    properties.get(CLASS_PROPERTY).map(String::as_str) == Some(MONITOR_CLASS)
+}
+
+// Scores text containing the characters of the query in order, ignoring case
+// and spaces. Consecutive characters and ones at the start of words score
+// higher, so "hd" prefers "HD Audio" over "Headphones".
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut start = 0;
+    let mut previous = None;
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let i = start + text[start..].iter().position(|&x| x == c)?;
+        score += 1;
+        if previous.is_some_and(|p: usize| p + 1 == i) {
+            score += 5;
+        }
+        if i == 0 || !text[i - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(i);
+        start = i + 1;
+    }
+    Some(score)
+}
+
+// Battery level of a bluetooth device in percent, given either as a
+// percentage or as a fraction.
+pub fn battery_level(properties: &BTreeMap<String, String>) -> Option<u32> {
+    let value = properties.get(BATTERY_PROPERTY)?.trim();
+    let level = value.trim_end_matches('%').trim().parse::<f64>().ok()?;
+    if value.ends_with('%') || level > 1.0 {
+        Some(level.round() as u32)
+    } else {
+        Some((level * 100.0).round() as u32)
+    }
+}
+
+// Shell style glob supporting `*` and `?`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
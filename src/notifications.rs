@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+const DESTINATION: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+const APP_NAME: &str = "Audio Select";
+
+static PENDING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+// Shows a desktop notification through org.freedesktop.Notifications on the
+// session bus. It's sent from its own thread so a missing or slow notification
+// server never holds up the popup.
+pub fn show(summary: &str, body: &str, icon: &str) {
+    let (summary, body, icon) = (summary.to_string(), body.to_string(), icon.to_string());
+    let handle = thread::spawn(move || {
+        if let Err(e) = send(&summary, &body, &icon) {
+            eprintln!("Failed to show notification: {}", e);
+        }
+    });
+    let mut pending = PENDING.lock().unwrap();
+    pending.retain(|x| !x.is_finished());
+    pending.push(handle);
+}
+
+// Waits for notifications still being sent, before exiting.
+pub fn flush() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    for handle in pending {
+        let _ = handle.join();
+    }
+}
+
+fn send(summary: &str, body: &str, icon: &str) -> zbus::Result<()> {
+    let connection = Connection::session()?;
+    let actions: Vec<&str> = Vec::new();
+    let hints: HashMap<&str, Value> = HashMap::new();
+    let expire_timeout = -1; // the server decides
+    connection.call_method(
+        Some(DESTINATION),
+        PATH,
+        Some(DESTINATION),
+        "Notify",
+        &(
+            APP_NAME,
+            0u32,
+            icon,
+            summary,
+            body,
+            actions,
+            hints,
+            expire_timeout,
+        ),
+    )?;
+    Ok(())
+}
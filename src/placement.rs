@@ -0,0 +1,205 @@
+use druid::{Data, Lens, Point, Rect, Screen, Size};
+use mouse_position::mouse_position::Mouse;
+use serde::{Deserialize, Serialize};
+
+// Space kept between the popup and the cursor or the edges of the screen
+const MARGIN: f64 = 5.0;
+const CURSOR_GAP: f64 = 10.0;
+const STATE_NAME: &str = "window";
+
+#[derive(Serialize, Clone, Copy, Deserialize, Debug, PartialEq, Data)]
+#[serde(rename_all = "snake_case")]
+pub enum PlacementMode {
+    Mouse,
+    Centered,
+    Anchored,
+    Fixed,
+    Remember,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Debug, PartialEq, Data)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl PlacementMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mouse" => Some(PlacementMode::Mouse),
+            "centered" => Some(PlacementMode::Centered),
+            "anchored" => Some(PlacementMode::Anchored),
+            "fixed" => Some(PlacementMode::Fixed),
+            "remember" => Some(PlacementMode::Remember),
+            _ => None,
+        }
+    }
+}
+
+impl Anchor {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top-left" => Some(Anchor::TopLeft),
+            "top" => Some(Anchor::Top),
+            "top-right" => Some(Anchor::TopRight),
+            "left" => Some(Anchor::Left),
+            "right" => Some(Anchor::Right),
+            "bottom-left" => Some(Anchor::BottomLeft),
+            "bottom" => Some(Anchor::Bottom),
+            "bottom-right" => Some(Anchor::BottomRight),
+            _ => None,
+        }
+    }
+
+    // Where the popup sits on each axis: -1 at the left or top edge, 0 in the
+    // middle and 1 at the right or bottom edge.
+    fn sides(&self) -> (i8, i8) {
+        match self {
+            Anchor::TopLeft => (-1, -1),
+            Anchor::Top => (0, -1),
+            Anchor::TopRight => (1, -1),
+            Anchor::Left => (-1, 0),
+            Anchor::Right => (1, 0),
+            Anchor::BottomLeft => (-1, 1),
+            Anchor::Bottom => (0, 1),
+            Anchor::BottomRight => (1, 1),
+        }
+    }
+}
+
+// Where the popup opens. The offset moves an anchored popup away from the
+// edges it's anchored to, and position is used by the fixed mode.
+#[derive(Serialize, Clone, Deserialize, Debug, PartialEq, Data, Lens)]
+#[serde(default)]
+pub struct Placement {
+    pub mode: PlacementMode,
+    pub anchor: Anchor,
+    pub offset: (f64, f64),
+    pub position: (f64, f64),
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            mode: PlacementMode::Mouse,
+            anchor: Anchor::TopRight,
+            offset: (0.0, 0.0),
+            position: (0.0, 0.0),
+        }
+    }
+}
+
+// The position the popup was closed at, kept in window.toml next to the config.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+struct SavedPosition {
+    position: Option<(f64, f64)>,
+}
+
+pub fn get_position(size: Size, placement: &Placement) -> (f64, f64) {
+    match placement.mode {
+        PlacementMode::Mouse => at_mouse(size),
+        PlacementMode::Centered => {
+            let area = work_area(mouse());
+            let origin = area.center() - size.to_vec2() / 2.0;
+            (origin.x, origin.y)
+        }
+        PlacementMode::Anchored => anchored(size, placement),
+        PlacementMode::Fixed => placement.position,
+        PlacementMode::Remember => match last_position() {
+            Some(position) => clamp(work_area(Some(position)), position, size),
+            None => at_mouse(size),
+        },
+    }
+}
+
+// Saves where the popup is for the next launch in the remember mode.
+pub fn remember(position: Point) {
+    let saved = SavedPosition {
+        position: Some((position.x, position.y)),
+    };
+    if let Err(e) = confy::store("audio-select", Some(STATE_NAME), saved) {
+        eprintln!("Failed to store the window position: {}", e);
+    }
+}
+
+fn last_position() -> Option<Point> {
+    confy::load::<SavedPosition>("audio-select", Some(STATE_NAME))
+        .ok()?
+        .position
+        .map(Point::from)
+}
+
+fn mouse() -> Option<Point> {
+    match Mouse::get_mouse_position() {
+        Mouse::Position { x, y } => Some(Point::new(x as f64, y as f64)),
+        Mouse::Error => None,
+    }
+}
+
+// Opens the popup right under the cursor, or above it in the lower half of the
+// monitor, kept inside that monitor's work area.
+fn at_mouse(size: Size) -> (f64, f64) {
+    let point = match mouse() {
+        Some(point) => point,
+        None => {
+            let area = work_area(None);
+            return clamp(area, area.center() - size.to_vec2() / 2.0, size);
+        }
+    };
+    let area = work_area(Some(point));
+    let top = if point.y < area.center().y {
+        point.y + CURSOR_GAP
+    } else {
+        point.y - size.height - CURSOR_GAP
+    };
+    clamp(area, Point::new(point.x - size.width / 2.0, top), size)
+}
+
+// Places the popup against an edge or corner of the monitor under the cursor.
+fn anchored(size: Size, placement: &Placement) -> (f64, f64) {
+    let area = work_area(mouse());
+    let (horizontal, vertical) = placement.anchor.sides();
+    let along = |side: i8, start: f64, end: f64, length: f64, offset: f64| match side {
+        -1 => start + offset,
+        1 => end - length - offset,
+        _ => (start + end - length) / 2.0 + offset,
+    };
+    (
+        along(horizontal, area.x0, area.x1, size.width, placement.offset.0),
+        along(vertical, area.y0, area.y1, size.height, placement.offset.1),
+    )
+}
+
+// The part of the monitor containing the point that isn't taken by docks and
+// bars, the primary monitor's without a point or when no monitor contains it.
+pub fn work_area(point: Option<Point>) -> Rect {
+    let monitors = Screen::get_monitors();
+    point
+        .and_then(|point| monitors.iter().find(|x| x.virtual_rect().contains(point)))
+        .or_else(|| monitors.iter().find(|x| x.is_primary()))
+        .or_else(|| monitors.first())
+        .map_or_else(Screen::get_display_rect, |x| x.virtual_work_rect())
+}
+
+// Moves a window at the position inside the area, showing its top left corner
+// if it doesn't fit.
+fn clamp(area: Rect, position: Point, size: Size) -> (f64, f64) {
+    (
+        position
+            .x
+            .min(area.x1 - size.width - MARGIN)
+            .max(area.x0 + MARGIN),
+        position
+            .y
+            .min(area.y1 - size.height - MARGIN)
+            .max(area.y0 + MARGIN),
+    )
+}
@@ -1,19 +1,96 @@
 use pulse::callbacks::ListResult;
-use pulse::context::introspect::{ServerInfo, SinkInfo, SourceInfo};
+use pulse::context::introspect::{
+    CardInfo, Introspector, ModuleInfo, ServerInfo, SinkInfo, SinkInputInfo, SourceInfo,
+    SourceOutputInfo,
+};
+use pulse::context::subscribe::InterestMaskSet;
 use pulse::context::Context;
+use pulse::def::{PortAvailable, SinkState, SourceState};
 use pulse::mainloop::standard::{IterateResult, Mainloop};
-use pulse::operation::State;
+use pulse::operation::{Operation, State};
 use pulse::proplist::Proplist;
-use std::cell::RefCell;
+use pulse::volume::{ChannelVolumes, Volume};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 use std::ops::Deref;
 use std::rc::Rc;
 use std::vec::Vec;
 
+pub use pulse::context::subscribe::{Facility, Operation as Change};
+
+pub const MAX_VOLUME_PERCENT: u32 = 150;
+
 pub enum PulseWrapperError {
     Ok,
     Err,
 }
 
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    pub card: Option<u32>,
+    pub channels: u8,
+    pub volume: u32, // percent, averaged over channels
+    pub mute: bool,
+    pub running: bool,
+    pub suspended: bool,
+    pub ports: Vec<Choice>,
+    pub active_port: Option<String>,
+    pub sample_spec: String,
+    pub channel_map: String,
+    pub latency: u64,            // microseconds
+    pub configured_latency: u64, // microseconds
+    pub owner_module: Option<u32>,
+    pub driver: String,
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Card {
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    pub profiles: Vec<Choice>,
+    pub active_profile: Option<String>,
+}
+
+// A port of a device or a profile of a card.
+#[derive(Clone, Debug)]
+pub struct Choice {
+    pub name: String,
+    pub description: String,
+    pub available: bool,
+}
+
+// A playing (sink input) or recording (source output) stream. `device` is the
+// index of the sink or source it's connected to.
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+    pub index: u32,
+    pub device: u32,
+    pub properties: BTreeMap<String, String>,
+}
+
+// Something that happened to a sink, source, stream or card on the server.
+#[derive(Clone, Copy, Debug)]
+pub struct ServerEvent {
+    pub facility: Facility,
+    pub change: Change,
+    pub index: u32, // of the sink, source, stream or card
+}
+
+// The parts of a device that change without it being added or removed.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceLevels {
+    pub volume: u32, // percent, averaged over channels
+    pub mute: bool,
+    pub running: bool,
+    pub suspended: bool,
+}
+
 pub struct PulseWrapper {
     mainloop: Rc<RefCell<Mainloop>>,
     context: Rc<RefCell<Context>>,
@@ -86,49 +163,172 @@ impl PulseWrapper {
         }
     }
 
-    pub fn get_sources(&self) -> Vec<(String, String)> {
+    pub fn get_sources(&self) -> Vec<DeviceInfo> {
         let result = Rc::new(RefCell::new(Vec::new()));
         let result2 = Rc::clone(&result);
         let op = self.context.borrow().introspect().get_source_info_list(
             move |x: ListResult<&SourceInfo>| {
                 if let ListResult::Item(e) = x {
-                    let name = String::from(e.name.as_ref().unwrap().deref());
-                    let description = String::from(e.description.as_ref().unwrap().deref());
-                    result2.borrow_mut().push((name, description));
+                    result2.borrow_mut().push(DeviceInfo {
+                        index: e.index,
+                        name: String::from(e.name.as_ref().unwrap().deref()),
+                        description: String::from(e.description.as_ref().unwrap().deref()),
+                        card: e.card,
+                        channels: e.volume.len(),
+                        volume: volume_percent(&e.volume),
+                        mute: e.mute,
+                        running: e.state == SourceState::Running,
+                        suspended: e.state == SourceState::Suspended,
+                        ports: e
+                            .ports
+                            .iter()
+                            .map(|x| Choice {
+                                name: optional_string(&x.name),
+                                description: optional_string(&x.description),
+                                available: x.available != PortAvailable::No,
+                            })
+                            .collect(),
+                        active_port: e.active_port.as_ref().map(|x| optional_string(&x.name)),
+                        sample_spec: e.sample_spec.print(),
+                        channel_map: e.channel_map.print(),
+                        latency: e.latency.0,
+                        configured_latency: e.configured_latency.0,
+                        owner_module: e.owner_module,
+                        driver: optional_string(&e.driver),
+                        properties: proplist_to_map(&e.proplist),
+                    });
                 }
             },
         );
-        while op.get_state() == State::Running {
-            match self.mainloop.borrow_mut().iterate(true) {
-                IterateResult::Quit(_) | IterateResult::Err(_) => {
-                    eprintln!("Iterate state was not success, quitting...");
-                }
-                IterateResult::Success(_) => {}
-            }
-        }
+        self.wait_for(&op);
         Rc::try_unwrap(result).unwrap().into_inner()
     }
 
-    pub fn get_sinks(&self) -> Vec<(String, String)> {
+    pub fn get_source_levels(&self, index: u32) -> Option<DeviceLevels> {
+        let result = Rc::new(Cell::new(None));
+        let result2 = Rc::clone(&result);
+        let op = self.context.borrow().introspect().get_source_info_by_index(
+            index,
+            move |x: ListResult<&SourceInfo>| {
+                if let ListResult::Item(e) = x {
+                    result2.set(Some(DeviceLevels {
+                        volume: volume_percent(&e.volume),
+                        mute: e.mute,
+                        running: e.state == SourceState::Running,
+                        suspended: e.state == SourceState::Suspended,
+                    }));
+                }
+            },
+        );
+        self.wait_for(&op);
+        result.get()
+    }
+
+    pub fn get_sink_levels(&self, index: u32) -> Option<DeviceLevels> {
+        let result = Rc::new(Cell::new(None));
+        let result2 = Rc::clone(&result);
+        let op = self.context.borrow().introspect().get_sink_info_by_index(
+            index,
+            move |x: ListResult<&SinkInfo>| {
+                if let ListResult::Item(e) = x {
+                    result2.set(Some(DeviceLevels {
+                        volume: volume_percent(&e.volume),
+                        mute: e.mute,
+                        running: e.state == SinkState::Running,
+                        suspended: e.state == SinkState::Suspended,
+                    }));
+                }
+            },
+        );
+        self.wait_for(&op);
+        result.get()
+    }
+
+    pub fn get_sinks(&self) -> Vec<DeviceInfo> {
         let result = Rc::new(RefCell::new(Vec::new()));
         let result2 = Rc::clone(&result);
         let op = self.context.borrow().introspect().get_sink_info_list(
             move |x: ListResult<&SinkInfo>| {
+                if let ListResult::Item(e) = x {
+                    result2.borrow_mut().push(DeviceInfo {
+                        index: e.index,
+                        name: String::from(e.name.as_ref().unwrap().deref()),
+                        description: String::from(e.description.as_ref().unwrap().deref()),
+                        card: e.card,
+                        channels: e.volume.len(),
+                        volume: volume_percent(&e.volume),
+                        mute: e.mute,
+                        running: e.state == SinkState::Running,
+                        suspended: e.state == SinkState::Suspended,
+                        ports: e
+                            .ports
+                            .iter()
+                            .map(|x| Choice {
+                                name: optional_string(&x.name),
+                                description: optional_string(&x.description),
+                                available: x.available != PortAvailable::No,
+                            })
+                            .collect(),
+                        active_port: e.active_port.as_ref().map(|x| optional_string(&x.name)),
+                        sample_spec: e.sample_spec.print(),
+                        channel_map: e.channel_map.print(),
+                        latency: e.latency.0,
+                        configured_latency: e.configured_latency.0,
+                        owner_module: e.owner_module,
+                        driver: optional_string(&e.driver),
+                        properties: proplist_to_map(&e.proplist),
+                    });
+                }
+            },
+        );
+        self.wait_for(&op);
+        Rc::try_unwrap(result).unwrap().into_inner()
+    }
+
+    pub fn get_cards(&self) -> Vec<Card> {
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let result2 = Rc::clone(&result);
+        let op = self.context.borrow().introspect().get_card_info_list(
+            move |x: ListResult<&CardInfo>| {
                 if let ListResult::Item(e) = x {
                     let name = String::from(e.name.as_ref().unwrap().deref());
-                    let description = String::from(e.description.as_ref().unwrap().deref());
-                    result2.borrow_mut().push((name, description));
+                    result2.borrow_mut().push(Card {
+                        index: e.index,
+                        description: e
+                            .proplist
+                            .get_str(pulse::proplist::properties::DEVICE_DESCRIPTION)
+                            .unwrap_or_else(|| name.clone()),
+                        name,
+                        profiles: e
+                            .profiles
+                            .iter()
+                            .map(|x| Choice {
+                                name: optional_string(&x.name),
+                                description: optional_string(&x.description),
+                                available: x.available,
+                            })
+                            .collect(),
+                        active_profile: e.active_profile.as_ref().map(|x| optional_string(&x.name)),
+                    });
                 }
             },
         );
-        while op.get_state() == State::Running {
-            match self.mainloop.borrow_mut().iterate(true) {
-                IterateResult::Quit(_) | IterateResult::Err(_) => {
-                    eprintln!("Iterate state was not success, quitting...");
+        self.wait_for(&op);
+        Rc::try_unwrap(result).unwrap().into_inner()
+    }
+
+    pub fn get_module_name(&self, index: u32) -> Option<String> {
+        let result = Rc::new(RefCell::new(None));
+        let result2 = Rc::clone(&result);
+        let op = self.context.borrow().introspect().get_module_info(
+            index,
+            move |x: ListResult<&ModuleInfo>| {
+                if let ListResult::Item(e) = x {
+                    *result2.borrow_mut() = Some(optional_string(&e.name));
                 }
-                IterateResult::Success(_) => {}
-            }
-        }
+            },
+        );
+        self.wait_for(&op);
         Rc::try_unwrap(result).unwrap().into_inner()
     }
 
@@ -147,41 +347,210 @@ impl PulseWrapper {
                 *source_clone.borrow_mut() = String::from(source_name);
                 *sink_clone.borrow_mut() = String::from(sink_name);
             });
-        while op.get_state() == State::Running {
-            match self.mainloop.borrow_mut().iterate(true) {
-                IterateResult::Quit(_) | IterateResult::Err(_) => {
-                    eprintln!("Iterate state was not success, quitting...");
-                    // return result;
-                }
-                IterateResult::Success(_) => {}
-            }
-        }
+        self.wait_for(&op);
         (
             Rc::try_unwrap(source).unwrap().into_inner(),
             Rc::try_unwrap(sink).unwrap().into_inner(),
         )
     }
 
-    pub fn set_default_source(&mut self, name: &str) {
-        let op = self.context.borrow_mut().set_default_source(name, |_| ());
-        while op.get_state() == State::Running {
-            match self.mainloop.borrow_mut().iterate(true) {
-                IterateResult::Quit(_) | IterateResult::Err(_) => {
-                    eprintln!("Iterate state was not success, quitting...");
-                    // return result;
+    pub fn set_default_source(&mut self, name: &str) -> PulseWrapperError {
+        let success = Rc::new(Cell::new(false));
+        let success2 = Rc::clone(&success);
+        let op = self
+            .context
+            .borrow_mut()
+            .set_default_source(name, move |x| success2.set(x));
+        self.wait_for(&op);
+        to_result(success.get())
+    }
+
+    pub fn set_default_sink(&mut self, name: &str) -> PulseWrapperError {
+        let success = Rc::new(Cell::new(false));
+        let success2 = Rc::clone(&success);
+        let op = self
+            .context
+            .borrow_mut()
+            .set_default_sink(name, move |x| success2.set(x));
+        self.wait_for(&op);
+        to_result(success.get())
+    }
+
+    pub fn set_card_profile(&mut self, card: &str, profile: &str) -> PulseWrapperError {
+        self.run(|introspect, callback| {
+            introspect.set_card_profile_by_name(card, profile, callback)
+        })
+    }
+
+    pub fn set_source_port(&mut self, name: &str, port: &str) -> PulseWrapperError {
+        self.run(|introspect, callback| introspect.set_source_port_by_name(name, port, callback))
+    }
+
+    pub fn set_sink_port(&mut self, name: &str, port: &str) -> PulseWrapperError {
+        self.run(|introspect, callback| introspect.set_sink_port_by_name(name, port, callback))
+    }
+
+    pub fn set_source_mute(&mut self, name: &str, mute: bool) -> PulseWrapperError {
+        self.run(|introspect, callback| introspect.set_source_mute_by_name(name, mute, callback))
+    }
+
+    pub fn set_sink_mute(&mut self, name: &str, mute: bool) -> PulseWrapperError {
+        self.run(|introspect, callback| introspect.set_sink_mute_by_name(name, mute, callback))
+    }
+
+    // Sets every channel to the same volume.
+    pub fn set_source_volume(&mut self, name: &str, percent: u32) -> PulseWrapperError {
+        let channels = match self.get_sources().into_iter().find(|x| x.name == name) {
+            Some(device) => device.channels,
+            None => return PulseWrapperError::Err,
+        };
+        let volumes = channel_volumes(channels, percent);
+        self.run(|introspect, callback| {
+            introspect.set_source_volume_by_name(name, &volumes, callback)
+        })
+    }
+
+    // Sets every channel to the same volume.
+    pub fn set_sink_volume(&mut self, name: &str, percent: u32) -> PulseWrapperError {
+        let channels = match self.get_sinks().into_iter().find(|x| x.name == name) {
+            Some(device) => device.channels,
+            None => return PulseWrapperError::Err,
+        };
+        let volumes = channel_volumes(channels, percent);
+        self.run(|introspect, callback| {
+            introspect.set_sink_volume_by_name(name, &volumes, callback)
+        })
+    }
+
+    pub fn get_sink_inputs(&self) -> Vec<StreamInfo> {
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let result2 = Rc::clone(&result);
+        let op = self.context.borrow().introspect().get_sink_input_info_list(
+            move |x: ListResult<&SinkInputInfo>| {
+                if let ListResult::Item(e) = x {
+                    result2.borrow_mut().push(StreamInfo {
+                        index: e.index,
+                        device: e.sink,
+                        properties: proplist_to_map(&e.proplist),
+                    });
                 }
-                IterateResult::Success(_) => {}
+            },
+        );
+        self.wait_for(&op);
+        Rc::try_unwrap(result).unwrap().into_inner()
+    }
+
+    pub fn get_source_outputs(&self) -> Vec<StreamInfo> {
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let result2 = Rc::clone(&result);
+        let op = self
+            .context
+            .borrow()
+            .introspect()
+            .get_source_output_info_list(move |x: ListResult<&SourceOutputInfo>| {
+                if let ListResult::Item(e) = x {
+                    result2.borrow_mut().push(StreamInfo {
+                        index: e.index,
+                        device: e.source,
+                        properties: proplist_to_map(&e.proplist),
+                    });
+                }
+            });
+        self.wait_for(&op);
+        Rc::try_unwrap(result).unwrap().into_inner()
+    }
+
+    pub fn move_sink_input(&mut self, index: u32, sink: &str) -> PulseWrapperError {
+        self.run(|introspect, callback| introspect.move_sink_input_by_name(index, sink, callback))
+    }
+
+    pub fn move_source_output(&mut self, index: u32, source: &str) -> PulseWrapperError {
+        self.run(|introspect, callback| {
+            introspect.move_source_output_by_name(index, source, callback)
+        })
+    }
+
+    // Moves every playing stream to the sink.
+    pub fn move_sink_inputs(&mut self, sink: &str) -> PulseWrapperError {
+        let mut result = PulseWrapperError::Ok;
+        for stream in self.get_sink_inputs() {
+            if let PulseWrapperError::Err = self.move_sink_input(stream.index, sink) {
+                result = PulseWrapperError::Err;
             }
         }
+        result
     }
 
-    pub fn set_default_sink(&mut self, name: &str) {
-        let op = self.context.borrow_mut().set_default_sink(name, |_| ());
+    // Moves every recording stream to the source.
+    pub fn move_source_outputs(&mut self, source: &str) -> PulseWrapperError {
+        let mut result = PulseWrapperError::Ok;
+        for stream in self.get_source_outputs() {
+            if let PulseWrapperError::Err = self.move_source_output(stream.index, source) {
+                result = PulseWrapperError::Err;
+            }
+        }
+        result
+    }
+
+    // Asks the server for events about devices, streams, cards and defaults.
+    // They're passed to the callback from within wait_for_events.
+    pub fn subscribe(
+        &mut self,
+        mut callback: impl FnMut(ServerEvent) + 'static,
+    ) -> PulseWrapperError {
+        self.context
+            .borrow_mut()
+            .set_subscribe_callback(Some(Box::new(move |facility, change, index| {
+                if let (Some(facility), Some(change)) = (facility, change) {
+                    callback(ServerEvent {
+                        facility,
+                        change,
+                        index,
+                    });
+                }
+            })));
+        let mask = InterestMaskSet::SINK
+            | InterestMaskSet::SOURCE
+            | InterestMaskSet::SINK_INPUT
+            | InterestMaskSet::SOURCE_OUTPUT
+            | InterestMaskSet::CARD
+            | InterestMaskSet::SERVER;
+        let success = Rc::new(Cell::new(false));
+        let success2 = Rc::clone(&success);
+        let op = self
+            .context
+            .borrow_mut()
+            .subscribe(mask, move |x| success2.set(x));
+        self.wait_for(&op);
+        to_result(success.get())
+    }
+
+    // Blocks until the server sends something.
+    pub fn wait_for_events(&mut self) -> PulseWrapperError {
+        match self.mainloop.borrow_mut().iterate(true) {
+            IterateResult::Success(_) => PulseWrapperError::Ok,
+            IterateResult::Quit(_) | IterateResult::Err(_) => PulseWrapperError::Err,
+        }
+    }
+
+    // Runs an introspection call and waits for the server to acknowledge it.
+    fn run(
+        &self,
+        f: impl FnOnce(&mut Introspector, Option<Box<dyn FnMut(bool)>>) -> Operation<dyn FnMut(bool)>,
+    ) -> PulseWrapperError {
+        let success = Rc::new(Cell::new(false));
+        let success2 = Rc::clone(&success);
+        let mut introspect = self.context.borrow().introspect();
+        let op = f(&mut introspect, Some(Box::new(move |x| success2.set(x))));
+        self.wait_for(&op);
+        to_result(success.get())
+    }
+
+    fn wait_for<F: ?Sized>(&self, op: &Operation<F>) {
         while op.get_state() == State::Running {
             match self.mainloop.borrow_mut().iterate(true) {
                 IterateResult::Quit(_) | IterateResult::Err(_) => {
                     eprintln!("Iterate state was not success, quitting...");
-                    // return result;
                 }
                 IterateResult::Success(_) => {}
             }
@@ -189,6 +558,40 @@ impl PulseWrapper {
     }
 }
 
+fn to_result(success: bool) -> PulseWrapperError {
+    if success {
+        PulseWrapperError::Ok
+    } else {
+        PulseWrapperError::Err
+    }
+}
+
+fn channel_volumes(channels: u8, percent: u32) -> ChannelVolumes {
+    let percent = percent.min(MAX_VOLUME_PERCENT) as u64;
+    let mut volumes = ChannelVolumes::default();
+    volumes.set(
+        channels,
+        Volume((Volume::NORMAL.0 as u64 * percent / 100) as u32),
+    );
+    volumes
+}
+
+fn volume_percent(volumes: &ChannelVolumes) -> u32 {
+    let normal = Volume::NORMAL.0 as u64;
+    ((volumes.avg().0 as u64 * 100 + normal / 2) / normal) as u32
+}
+
+fn optional_string(value: &Option<Cow<str>>) -> String {
+    value.as_deref().unwrap_or_default().to_string()
+}
+
+fn proplist_to_map(proplist: &Proplist) -> BTreeMap<String, String> {
+    proplist
+        .iter()
+        .filter_map(|key| proplist.get_str(&key).map(|value| (key, value)))
+        .collect()
+}
+
 impl Drop for PulseWrapper {
     fn drop(&mut self) {
         if self.connected {
@@ -1,6 +1,6 @@
 use crate::data::AppState;
 pub use druid::theme::*;
-use druid::{Color, Env, Insets, Key};
+use druid::{Color, Env, FontDescriptor, FontFamily, Insets, Key};
 
 pub fn grid(m: f64) -> f64 {
     GRID * m
@@ -29,6 +29,15 @@ pub const MENU_BUTTON_FG_INACTIVE: Key<Color> = Key::new("app.menu-fg-inactive")
 // pub const TEXT_SIZE_SMALL: Key<f64> = Key::new("app.text-size-small");
 
 pub const ICON_COLOR: Key<Color> = Key::new("app.icon-color");
+// Height of a device row, which follows the text size
+pub const DEVICE_HEIGHT: Key<f64> = Key::new("app.device-height");
+
+// The text size the popup's sizes are measured at
+const BASE_TEXT_SIZE: f64 = 15.0;
+
+pub fn text_scale(state: &AppState) -> f64 {
+    state.text_size / BASE_TEXT_SIZE
+}
 // pub const ICON_SIZE_SMALL: Size = Size::new(14.0, 14.0);
 // pub const ICON_SIZE_MEDIUM: Size = Size::new(16.0, 16.0);
 // pub const ICON_SIZE_LARGE: Size = Size::new(22.0, 22.0);
@@ -90,6 +99,18 @@ pub fn setup(env: &mut Env, state: &AppState) {
     // env.set(TEXT_SIZE_NORMAL, 13.0);
     // env.set(TEXT_SIZE_LARGE, 16.0);
 
+    let family = if state.font.is_empty() {
+        FontFamily::SYSTEM_UI
+    } else {
+        FontFamily::new_unchecked(state.font.as_str())
+    };
+    env.set(
+        UI_FONT,
+        FontDescriptor::new(family).with_size(state.text_size),
+    );
+    env.set(TEXT_SIZE_NORMAL, state.text_size);
+    env.set(DEVICE_HEIGHT, crate::ui::DEVICE_HEIGHT * text_scale(state));
+
     env.set(BASIC_WIDGET_HEIGHT, 16.0);
     env.set(WIDE_WIDGET_WIDTH, grid(12.0));
     env.set(BORDERED_WIDGET_HEIGHT, grid(4.0));
@@ -1,34 +1,237 @@
-use crate::data::{AppState, AudioDeviceState, AudioDeviceType};
+use crate::data::{
+    AppState, AudioDeviceState, AudioDeviceType, DefaultVolume, DeviceGroup, GroupBy,
+    GroupedDevices, SortMode,
+};
+use crate::keys::KeyAction;
+use crate::matching::{battery_level, DeviceIdentity};
+use crate::placement::{work_area, Anchor, Placement, PlacementMode};
+use crate::theme;
+use druid::lens::LensExt;
+use druid::menu::MenuEventCtx;
 use druid::widget::{
-    Button, Checkbox, CrossAxisAlignment, Either, Flex, Label, LensWrap, List, Scroll, SizedBox,
-    Split, TextBox,
+    Button, Checkbox, Controller, CrossAxisAlignment, Either, EnvScope, Flex, Image, Label,
+    LensWrap, LineBreaking, List, Painter, RadioGroup, Scroll, SizedBox, Slider, Stepper, Svg,
+    SvgData, Tabs, TextBox,
+};
+use druid::{
+    lens, Application, BoxConstraints, Code, Cursor, Data, Env, Event, EventCtx, ImageBuf, KbKey,
+    KeyEvent, LayoutCtx, Lens, LifeCycle, LifeCycleCtx, Menu, MenuItem, PaintCtx, Point,
+    RenderContext, Selector, Size, UnitPoint, UpdateCtx, Widget, WidgetExt, WidgetId, WindowConfig,
 };
-use druid::{Env, EventCtx, Size, Widget, WidgetExt, WindowConfig};
+use std::fs;
+
+const SELECT_DEVICE: Selector<(AudioDeviceType, String)> =
+    Selector::new("audio-select.select-device");
+const TOGGLE_GROUP: Selector<String> = Selector::new("audio-select.toggle-group");
+const MOVE_DEVICE: Selector<(AudioDeviceType, String, isize)> =
+    Selector::new("audio-select.move-device");
+const APPLY_SCENE: Selector<String> = Selector::new("audio-select.apply-scene");
+const SHOW_DEVICE_MENU: Selector<(AudioDeviceState, Point)> =
+    Selector::new("audio-select.show-device-menu");
+const INSPECT_DEVICE: Selector<(AudioDeviceType, String)> =
+    Selector::new("audio-select.inspect-device");
+// Handled by the window, which remembers where it was before closing
+pub const CLOSE_POPUP: Selector = Selector::new("audio-select.close-popup");
+const FOCUS_RENAME: Selector = Selector::new("audio-select.focus-rename");
+const FINISH_RENAME: Selector = Selector::new("audio-select.finish-rename");
+
+const DRAG_HANDLE_WIDTH: f64 = 30.0;
+const ICON_SIZE: f64 = 24.0;
+// Sizes of the parts of the popup, to fit the window to them
+pub const DEVICE_HEIGHT: f64 = 45.0; // at the base text size
+const DEVICE_PADDING: f64 = 5.0;
+const ROW_HEIGHT: f64 = 40.0;
+const GROUP_HEADER_HEIGHT: f64 = 30.0;
+const CHARACTER_WIDTH: f64 = 7.5;
+const BUTTON_INSETS: f64 = 16.0;
+const SCROLLBAR_WIDTH: f64 = 12.0;
 
 pub fn build_ui() -> impl Widget<AppState> {
-    Either::new(
+    let ui = Either::new(
         |data, _env| data.ready,
-        build_devices_screen(),
+        Either::new(
+            |data: &AppState, _env| data.pinned,
+            build_mixer(),
+            build_devices_screen(),
+        ),
         Label::new(|data: &String, _env: &_| format!("{}:", data))
             .lens(AppState::not_ready_string)
             .center(),
     )
+    .controller(FitToContent::default());
+    // Set up again from the data so theme changes in the settings show here
+    EnvScope::new(theme::setup, ui.background(theme::WINDOW_BACKGROUND_COLOR))
+}
+
+// The size that shows every device without scrolling, within the configured
+// bounds. The search is left out so the popup doesn't shrink while typing.
+pub fn window_size(data: &AppState) -> Size {
+    // Measured at the base text size, rows and labels grow with the text
+    let scale = theme::text_scale(data);
+    let row_height = ROW_HEIGHT * scale;
+    if data.pinned {
+        // A label and a slider for each default and the bottom row
+        return Size::new(data.min_window_size().width, row_height * 5.0);
+    }
+    let mut data = data.clone();
+    data.search.clear();
+    // The search, privacy and bottom rows and the two section headers
+    let mut height = row_height * 5.0;
+    if !data.scenes.is_empty() {
+        height += row_height;
+    }
+    let mut characters = 0;
+    for device_type in [AudioDeviceType::Source, AudioDeviceType::Sink] {
+        for group in data.grouped_devices(&device_type) {
+            if !group.title.is_empty() {
+                height += GROUP_HEADER_HEIGHT * scale;
+            }
+            if group.collapsed {
+                continue;
+            }
+            for device in group.devices.iter() {
+                height += DEVICE_HEIGHT * scale + 2.0 * DEVICE_PADDING;
+                // Room for the favourite and pair markers
+                let label = get_shortened_label(device.display_label()).chars().count() + 4;
+                characters = characters.max(label).max(badges(device).chars().count());
+            }
+        }
+    }
+    let width = characters as f64 * CHARACTER_WIDTH * scale
+        + ICON_SIZE
+        + 10.0
+        + 2.0 * DEVICE_PADDING
+        + BUTTON_INSETS
+        + SCROLLBAR_WIDTH;
+    let (min, max) = (data.min_window_size(), data.max_window_size());
+    Size::new(
+        width.max(min.width).min(max.width),
+        height.max(min.height).min(max.height),
+    )
+}
+
+// Resizes the popup as devices come and go. A popup above the cursor keeps
+// its bottom edge in place so it stays next to it.
+#[derive(Default)]
+struct FitToContent {
+    size: Size,
+}
+
+impl<W: Widget<AppState>> Controller<AppState, W> for FitToContent {
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.size = window_size(data);
+        }
+        child.lifecycle(ctx, event, data, env)
+    }
+
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &AppState,
+        data: &AppState,
+        env: &Env,
+    ) {
+        let size = window_size(data);
+        if size != self.size {
+            self.size = size;
+            let window = ctx.window();
+            let (position, old_size) = (window.get_position(), window.get_size());
+            let middle = work_area(Some(position)).center().y;
+            window.set_size(size);
+            if position.y + old_size.height / 2.0 > middle {
+                window.set_position((position.x, position.y + old_size.height - size.height));
+            }
+        }
+        child.update(ctx, old_data, data, env)
+    }
 }
 
 fn build_devices_screen() -> impl Widget<AppState> {
+    let scenes = Either::new(
+        |data: &AppState, _env| data.scenes.is_empty(),
+        SizedBox::empty(),
+        Scroll::new(
+            List::new(|| {
+                Button::new(|data: &String, _: &Env| data.clone())
+                    .on_click(|ctx, data: &mut String, _env| {
+                        ctx.submit_command(APPLY_SCENE.with(data.clone()));
+                    })
+                    .padding(5.0)
+            })
+            .horizontal()
+            .lens(AppState::scenes),
+        )
+        .horizontal(),
+    );
+    let privacy = Flex::row()
+        .with_child(
+            Button::new(|data: &AppState, _: &Env| {
+                if data.inputs_muted {
+                    String::from("Unmute inputs")
+                } else {
+                    String::from("Mute all inputs")
+                }
+            })
+            .on_click(|_ctx, data: &mut AppState, _env| {
+                let mute = !data.inputs_muted;
+                data.status = data.mute_inputs(mute).join("\n");
+            })
+            .padding(5.0),
+        )
+        .with_flex_child(
+            Label::new(|data: &AppState, _: &Env| {
+                if data.recording.is_empty() {
+                    String::from("Nothing is recording")
+                } else {
+                    format!("● Recording: {}", data.recording.join(", "))
+                }
+            })
+            .with_line_break_mode(LineBreaking::WordWrap),
+            1.0,
+        );
+    let status = Either::new(
+        |data: &AppState, _env| data.status.is_empty(),
+        SizedBox::empty(),
+        Label::new(|data: &AppState, _: &Env| data.status.clone())
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .padding(5.0),
+    );
+    let search = TextBox::new()
+        .with_placeholder("Type to search")
+        .expand_width()
+        .lens(AppState::search)
+        .padding(5.0);
     let body = Flex::column()
-        .with_child(Label::new("Input").padding(5.0).center())
+        .with_child(search)
+        .with_child(scenes)
+        .with_child(privacy)
+        .with_child(status)
+        .with_child(build_section_header("Input", AudioDeviceType::Source))
         .with_child(LensWrap::new(
-            List::new(build_device_button),
-            (AppState::default_source, AppState::sources),
+            List::new(build_device_group),
+            (
+                AppState::default_source,
+                GroupedDevices(AudioDeviceType::Source),
+            ),
         ))
-        .with_child(Label::new("Output").padding(5.0).center())
+        .with_child(build_section_header("Output", AudioDeviceType::Sink))
         .with_child(LensWrap::new(
-            List::new(build_device_button),
-            (AppState::default_sink, AppState::sinks),
+            List::new(build_device_group),
+            (
+                AppState::default_sink,
+                GroupedDevices(AudioDeviceType::Sink),
+            ),
         ));
     let settings_button = Button::new("Settings").on_click(|ctx, data: &mut AppState, env| {
-        data.close_on_leave = false;
         ctx.new_sub_window(
             WindowConfig::default()
                 // .set_level(WindowLevel::AppWindow)
@@ -42,93 +245,1107 @@ fn build_devices_screen() -> impl Widget<AppState> {
     let restart_buton = Button::new("Restart").on_click(|ctx, data: &mut AppState, _env| {
         data.restart_async(ctx.get_external_handle());
     });
+    let pause_lock_button = Either::new(
+        |data: &AppState, _env| data.locked_source.is_none() && data.locked_sink.is_none(),
+        SizedBox::empty(),
+        Button::new("Allow changes")
+            .on_click(|_ctx, data: &mut AppState, _env| data.pause_lock())
+            .disabled_if(|data, _env| data.is_lock_paused()),
+    );
 
-    // Flex::column()
-    //     .with_flex_child(Scroll::new(body).vertical(), 1.0)
-    //     .with_child(Flex::row().with_child(settings_button).with_child(restart_buton))
-    Split::rows(
-        Scroll::new(body).vertical(),
-        Flex::row()
-            .with_child(settings_button)
-            .with_child(restart_buton),
-    )
-    .split_point(0.9)
-    .bar_size(3.0)
-    .solid_bar(true)
+    Flex::column()
+        .with_flex_child(Scroll::new(body).vertical(), 1.0)
+        .with_child(
+            Flex::row()
+                .with_child(settings_button)
+                .with_child(restart_buton)
+                .with_child(pause_lock_button)
+                .with_child(
+                    Button::new("Pin").on_click(|_ctx, data: &mut AppState, _env| {
+                        data.set_pinned(true);
+                    }),
+                ),
+        )
+        .controller(DevicesController)
+        .controller(KeyboardController)
 }
 
-fn build_device_button() -> impl Widget<(String, AudioDeviceState)> {
-    Either::new(
-        |data, _env| data.1.hidden || !data.1.connected,
+// The pinned popup: the volume and mute of the default output and input.
+fn build_mixer() -> impl Widget<AppState> {
+    let mut column = Flex::column().cross_axis_alignment(CrossAxisAlignment::Start);
+    for device_type in [AudioDeviceType::Sink, AudioDeviceType::Source] {
+        let label_type = device_type.clone();
+        let mute_type = device_type.clone();
+        let click_type = device_type.clone();
+        let label = Label::new(move |data: &AppState, _env: &_| {
+            let name = data.default_of(&label_type);
+            data.device(&label_type, name)
+                .map_or(String::new(), |x| get_shortened_label(x.display_label()))
+        });
+        let mute_button = Button::new(move |data: &AppState, _env: &_| {
+            let name = data.default_of(&mute_type);
+            match data.device(&mute_type, name) {
+                Some(device) if device.muted => String::from("Unmute"),
+                _ => String::from("Mute"),
+            }
+        })
+        .on_click(move |_ctx, data: &mut AppState, _env| {
+            let name = data.default_of(&click_type).to_string();
+            if let Some(muted) = data.device(&click_type, &name).map(|x| x.muted) {
+                data.status = data.set_mute(&click_type, &name, !muted).join("\n");
+            }
+        });
+        column.add_child(label.padding(5.0));
+        column.add_child(
+            Flex::row()
+                .with_flex_child(
+                    Slider::new()
+                        .with_range(0.0, 100.0)
+                        .lens(DefaultVolume(device_type))
+                        .expand_width(),
+                    1.0,
+                )
+                .with_child(mute_button),
+        );
+    }
+    column
+        .with_child(
+            Button::new("Unpin").on_click(|_ctx, data: &mut AppState, _env| {
+                data.set_pinned(false);
+            }),
+        )
+        .padding(5.0)
+}
+
+fn build_section_header(title: &str, device_type: AudioDeviceType) -> impl Widget<AppState> {
+    let lock_type = device_type.clone();
+    let lock_button = Button::new(move |data: &AppState, _: &Env| {
+        if data.is_locked(&device_type) {
+            String::from("Locked")
+        } else {
+            String::from("Lock")
+        }
+    })
+    .on_click(move |_ctx, data: &mut AppState, _env| {
+        data.lock(&lock_type, !data.is_locked(&lock_type));
+    });
+    Flex::row()
+        .with_child(Label::new(title).padding(5.0))
+        .with_child(lock_button)
+        .center()
+}
+
+fn build_device_group() -> impl Widget<(String, DeviceGroup)> {
+    let header = Either::new(
+        |data: &(String, DeviceGroup), _env| data.1.title.is_empty(),
         SizedBox::empty(),
-        Button::new(|data: &(String, AudioDeviceState), _: &Env| {
-            get_shortened_label(&data.1.label)
+        Label::new(|data: &(String, DeviceGroup), _env: &_| {
+            let arrow = if data.1.collapsed { "▸" } else { "▾" };
+            format!("{} {}", arrow, data.1.title)
         })
-        .on_click(
-            |_: &mut EventCtx, data: &mut (String, AudioDeviceState), _: &Env| {
-                let mut pulsewrapper = data.1.pulsewrapper.borrow_mut();
-                match data.1.device_type {
-                    AudioDeviceType::Source => {
-                        pulsewrapper.set_default_source(&data.1.name);
-                    }
-                    AudioDeviceType::Sink => {
-                        pulsewrapper.set_default_sink(&data.1.name);
-                    }
+        .padding(5.0)
+        .on_click(|ctx, data: &mut (String, DeviceGroup), _env| {
+            ctx.submit_command(TOGGLE_GROUP.with(data.1.key.clone()));
+        }),
+    );
+    let devices = Either::new(
+        |data: &(String, DeviceGroup), _env| data.1.collapsed,
+        SizedBox::empty(),
+        LensWrap::new(
+            List::new(build_device_button),
+            (
+                lens!((String, DeviceGroup), 0),
+                lens!((String, DeviceGroup), 1).then(DeviceGroup::devices),
+            ),
+        ),
+    );
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(header)
+        .with_child(devices)
+}
+
+fn build_device_button() -> impl Widget<(String, AudioDeviceState)> {
+    let rename_id = WidgetId::next();
+    let rename = TextBox::new()
+        .with_placeholder("Label")
+        .lens(lens!((String, AudioDeviceState), 1).then(AudioDeviceState::label))
+        .controller(RenameBox)
+        .expand_width()
+        .align_vertical(UnitPoint::CENTER)
+        .fix_height(theme::DEVICE_HEIGHT)
+        .padding(5.0)
+        .with_id(rename_id);
+    let button = Button::new(|data: &(String, AudioDeviceState), _: &Env| {
+        let mut label = get_shortened_label(data.1.display_label());
+        if data.1.favourite {
+            label = format!("★ {}", label);
+        }
+        // Marks both sides of a pair that switches together.
+        if !data.1.partner.is_empty() && !data.1.unpaired {
+            label = format!("{} ⇄", label);
+        }
+        let badges = badges(&data.1);
+        if !badges.is_empty() {
+            label = format!("{}\n{}", label, badges);
+        }
+        label
+    })
+    .on_click(
+        |ctx: &mut EventCtx, data: &mut (String, AudioDeviceState), _: &Env| {
+            ctx.submit_command(
+                SELECT_DEVICE.with((data.1.device_type.clone(), data.1.name.clone())),
+            );
+        },
+    )
+    .disabled_if(|data, _env| data.0 == data.1.name)
+    .expand_width()
+    .fix_height(theme::DEVICE_HEIGHT);
+    let icon = DeviceIcon::default()
+        .lens(lens!((String, AudioDeviceState), 1).then(AudioDeviceState::icon_path))
+        .fix_size(ICON_SIZE, ICON_SIZE)
+        .padding((0.0, 0.0, 10.0, 0.0));
+    let button = Flex::row()
+        .with_child(icon)
+        .with_flex_child(button, 1.0)
+        .padding(DEVICE_PADDING)
+        .background(Painter::new(
+            |ctx, data: &(String, AudioDeviceState), env| {
+                if data.1.focused {
+                    let rect = ctx.size().to_rounded_rect(4.0);
+                    ctx.stroke(rect, &env.get(druid::theme::PRIMARY_LIGHT), 2.0);
                 }
-                data.0 = data.1.name.clone();
             },
-        )
-        .disabled_if(|data, _env| data.0 == data.1.name)
-        .fix_size(290.0, 45.0)
-        .padding(5.0),
+        ))
+        .controller(ScrollToFocus);
+    Either::new(
+        |data, _env| data.1.hidden || data.1.rule_hidden || !data.1.connected,
+        SizedBox::empty(),
+        Either::new(|data, _env| data.1.renaming, rename, button),
     )
+    .controller(DeviceMenu { rename_id })
 }
 
-fn build_config_menu() -> impl Widget<AppState> {
+// The state of a device in short, shown under its label.
+fn badges(device: &AudioDeviceState) -> String {
+    let mut badges = Vec::new();
+    if device.running {
+        badges.push(String::from("active"));
+    } else if device.suspended {
+        badges.push(String::from("suspended"));
+    }
+    if device.muted {
+        badges.push(String::from("muted"));
+    }
+    if let Some(level) = battery_level(&device.properties) {
+        badges.push(format!("battery {}%", level));
+    }
+    match device.apps {
+        0 => (),
+        1 => badges.push(String::from("in use by 1 app")),
+        n => badges.push(format!("in use by {} apps", n)),
+    }
+    badges.join(" · ")
+}
+
+// Shows the image at the path it's given, if it can be loaded.
+#[derive(Default)]
+struct DeviceIcon {
+    image: Option<Box<dyn Widget<String>>>,
+}
+
+impl DeviceIcon {
+    fn load(&mut self, path: &str) {
+        self.image = if path.ends_with(".svg") {
+            fs::read_to_string(path)
+                .ok()
+                .and_then(|x| x.parse::<SvgData>().ok())
+                .map(|x| Box::new(Svg::new(x)) as Box<dyn Widget<String>>)
+        } else {
+            ImageBuf::from_file(path)
+                .ok()
+                .map(|x| Box::new(Image::new(x)) as Box<dyn Widget<String>>)
+        };
+    }
+}
+
+impl Widget<String> for DeviceIcon {
+    fn event(&mut self, _ctx: &mut EventCtx, _event: &Event, _data: &mut String, _env: &Env) {}
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &String, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.load(data);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &String, data: &String, _env: &Env) {
+        if old_data != data {
+            self.load(data);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &String,
+        env: &Env,
+    ) -> Size {
+        if let Some(image) = &mut self.image {
+            image.layout(ctx, bc, data, env);
+        }
+        bc.max()
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &String, env: &Env) {
+        if let Some(image) = &mut self.image {
+            image.paint(ctx, data, env);
+        }
+    }
+}
+
+// Actions on a single device, shown on right click.
+fn build_device_menu(data: &AppState, device: &AudioDeviceState) -> Menu<AppState> {
+    let device_type = device.device_type.clone();
+    let name = device.name.clone();
+    let default = match device_type {
+        AudioDeviceType::Source => &data.default_source,
+        AudioDeviceType::Sink => &data.default_sink,
+    };
+    let choices = data.choices_of(&device_type, &name);
+    let action = |f: fn(&mut AppState, &AudioDeviceType, &str)| {
+        let (device_type, name) = (device_type.clone(), name.clone());
+        move |_ctx: &mut MenuEventCtx, data: &mut AppState, _env: &Env| f(data, &device_type, &name)
+    };
+
+    let mut ports = Menu::new("Port").enabled(!choices.ports.is_empty());
+    for port in choices.ports {
+        let (device_type, name) = (device_type.clone(), name.clone());
+        ports = ports.entry(
+            MenuItem::new(port.description)
+                .selected(choices.active_port.as_ref() == Some(&port.name))
+                .enabled(port.available)
+                .on_activate(move |_ctx, data: &mut AppState, _env| {
+                    data.status = data.set_port(&device_type, &name, &port.name).join("\n");
+                }),
+        );
+    }
+    let mut profiles = Menu::new("Profile").enabled(!choices.profiles.is_empty());
+    for profile in choices.profiles {
+        let (device_type, name) = (device_type.clone(), name.clone());
+        profiles = profiles.entry(
+            MenuItem::new(profile.description)
+                .selected(choices.active_profile.as_ref() == Some(&profile.name))
+                .enabled(profile.available)
+                .on_activate(move |_ctx, data: &mut AppState, _env| {
+                    data.status = data
+                        .set_profile(&device_type, &name, &profile.name)
+                        .join("\n");
+                }),
+        );
+    }
+
+    let mute = !device.muted;
+    let (mute_type, mute_name) = (device_type.clone(), name.clone());
+    Menu::empty()
+        .entry(
+            MenuItem::new("Set as default")
+                .enabled(*default != name)
+                .command(SELECT_DEVICE.with((device_type.clone(), name.clone()))),
+        )
+        .entry(
+            MenuItem::new(if mute { "Mute" } else { "Unmute" }).on_activate(
+                move |_ctx, data: &mut AppState, _env| {
+                    data.status = data.set_mute(&mute_type, &mute_name, mute).join("\n");
+                },
+            ),
+        )
+        .entry(MenuItem::new("Rename").on_activate(action(AppState::start_rename)))
+        .entry(MenuItem::new("Hide").on_activate(action(AppState::hide_device)))
+        .entry(
+            MenuItem::new("Favourite")
+                .selected(device.favourite)
+                .on_activate(action(AppState::toggle_favourite)),
+        )
+        .separator()
+        .entry(MenuItem::new("Move all streams here").on_activate(action(AppState::move_streams)))
+        .entry(ports)
+        .entry(profiles)
+        .separator()
+        .entry(
+            MenuItem::new("Show properties")
+                .command(INSPECT_DEVICE.with((device_type.clone(), name.clone()))),
+        )
+        .entry(
+            MenuItem::new("Copy PulseAudio name").on_activate(move |_ctx, _data, _env| {
+                Application::global().clipboard().put_string(&name)
+            }),
+        )
+}
+
+// Opens a window with everything the server reports about a device, as it is
+// at the moment.
+fn show_inspector(
+    ctx: &mut EventCtx,
+    data: &AppState,
+    env: &Env,
+    device_type: &AudioDeviceType,
+    name: &str,
+) {
+    let report = data
+        .inspect(device_type, name)
+        .unwrap_or_else(|| format!("{} is not connected", name));
+    let copied = report.clone();
     let body = Flex::column()
-        .with_child(Label::new("Settings"))
-        // .with_child(TextBox::new().lens(AppState::default_source))
-        .with_child(Label::new("Input Devices:"))
-        .with_child(List::new(build_device_config).lens(AppState::sources))
-        .with_child(Label::new("Output Devices:"))
-        .with_child(List::new(build_device_config).lens(AppState::sinks));
-    let save_button =
-        Button::new("Save").on_click(|_: &mut EventCtx, data: &mut AppState, _: &Env| {
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_flex_child(
+            Scroll::new(
+                Label::new(report)
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .padding(10.0),
+            )
+            .vertical(),
+            1.0,
+        )
+        .with_child(
+            Button::new("Copy to clipboard")
+                .on_click(move |_ctx, _data: &mut AppState, _env| {
+                    Application::global().clipboard().put_string(&copied)
+                })
+                .padding(5.0),
+        );
+    ctx.new_sub_window(
+        WindowConfig::default().window_size(Size::new(600.0, 500.0)),
+        body,
+        data.clone(),
+        env.clone(),
+    );
+}
+
+fn build_config_menu() -> impl Widget<AppState> {
+    let tabs = Tabs::new()
+        .with_tab("Devices", Scroll::new(build_devices_tab()).vertical())
+        .with_tab("Appearance", Scroll::new(build_appearance_tab()).vertical())
+        .with_tab("Behaviour", Scroll::new(build_behaviour_tab()).vertical())
+        .with_tab("Rules", Scroll::new(build_rules_tab()).vertical())
+        .controller(ConfigMenuController);
+    let apply_button = Button::new("Apply")
+        .on_click(|_: &mut EventCtx, data: &mut AppState, _: &Env| {
             data.save_config();
+        })
+        .disabled_if(|data: &AppState, _| !data.unsaved);
+    let revert_button = Button::new("Revert")
+        .on_click(|_: &mut EventCtx, data: &mut AppState, _: &Env| {
+            data.revert_config();
+        })
+        .disabled_if(|data: &AppState, _| !data.unsaved);
+    let reset_button =
+        Button::new("Reset to defaults").on_click(|_: &mut EventCtx, data: &mut AppState, _| {
+            data.reset_config();
         });
-    Split::rows(
-        Scroll::new(body).vertical(),
-        Flex::row().with_child(save_button),
+    let unsaved = Label::new(|data: &AppState, _: &Env| {
+        if data.unsaved {
+            String::from("Unsaved changes")
+        } else {
+            String::new()
+        }
+    });
+    let body = Flex::column().with_flex_child(tabs, 1.0).with_child(
+        Flex::row()
+            .with_child(apply_button)
+            .with_child(revert_button)
+            .with_child(reset_button)
+            .with_child(unsaved.padding(5.0)),
+    );
+    EnvScope::new(
+        theme::setup,
+        body.background(theme::WINDOW_BACKGROUND_COLOR),
     )
-    .split_point(0.9)
-    .bar_size(3.0)
-    .solid_bar(true)
 }
 
-fn build_device_config() -> impl Widget<AudioDeviceState> {
+fn build_devices_tab() -> impl Widget<AppState> {
+    Flex::column()
+        .with_child(Label::new("Sort devices by:"))
+        .with_child(
+            RadioGroup::row(vec![
+                ("Manual", SortMode::Manual),
+                ("Name", SortMode::Alphabetical),
+                ("Most used", SortMode::MostUsed),
+                ("Recently connected", SortMode::RecentlyConnected),
+            ])
+            .lens(AppState::sort_mode),
+        )
+        .with_child(Label::new("Group devices by:"))
+        .with_child(
+            RadioGroup::row(vec![
+                ("Nothing", GroupBy::Nothing),
+                ("Card", GroupBy::Card),
+                ("Bus", GroupBy::Bus),
+                ("Custom groups", GroupBy::Custom),
+            ])
+            .lens(AppState::group_by),
+        )
+        .with_child(Label::new("Input Devices:"))
+        .with_child(List::new(build_device_config).lens(AppState::sources))
+        .with_child(Label::new("Output Devices:"))
+        .with_child(List::new(build_device_config).lens(AppState::sinks))
+}
+
+fn build_appearance_tab() -> impl Widget<AppState> {
     Flex::column()
         .cross_axis_alignment(CrossAxisAlignment::Start)
         .with_child(
-            Label::new(|data: &String, _env: &_| format!("{}:", data))
-                .lens(AudioDeviceState::name)
+            Checkbox::new("Dark theme")
+                .lens(AppState::use_dark_theme)
                 .padding(5.0),
         )
+        .with_child(Label::new("Font:"))
         .with_child(
             TextBox::new()
+                .with_placeholder("System font")
+                .lens(AppState::font)
+                .padding(5.0),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(Label::new(|data: &AppState, _: &Env| {
+                    format!("Text size: {}", data.text_size)
+                }))
+                .with_child(
+                    Stepper::new()
+                        .with_range(8.0, 32.0)
+                        .with_step(1.0)
+                        .lens(AppState::text_size),
+                ),
+        )
+        .padding(5.0)
+}
+
+fn build_behaviour_tab() -> impl Widget<AppState> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Open the popup:"))
+        .with_child(
+            RadioGroup::row(vec![
+                ("At the mouse", PlacementMode::Mouse),
+                ("Centered", PlacementMode::Centered),
+                ("Anchored", PlacementMode::Anchored),
+                ("Fixed", PlacementMode::Fixed),
+                ("Where it was", PlacementMode::Remember),
+            ])
+            .lens(AppState::placement.then(Placement::mode)),
+        )
+        .with_child(Label::new("Anchored to:"))
+        .with_child(
+            RadioGroup::row(vec![
+                ("↖", Anchor::TopLeft),
+                ("↑", Anchor::Top),
+                ("↗", Anchor::TopRight),
+                ("←", Anchor::Left),
+                ("→", Anchor::Right),
+                ("↙", Anchor::BottomLeft),
+                ("↓", Anchor::Bottom),
+                ("↘", Anchor::BottomRight),
+            ])
+            .lens(AppState::placement.then(Placement::anchor)),
+        )
+        .with_child(Label::new("Close the popup when:"))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Checkbox::new("The pointer leaves it")
+                        .lens(AppState::close_on_leave)
+                        .padding(5.0),
+                )
+                .with_child(
+                    Checkbox::new("Another window is focused")
+                        .lens(AppState::close_on_focus_loss)
+                        .padding(5.0),
+                ),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(Label::new(|data: &AppState, _: &Env| {
+                    format!("Close delay: {} ms", data.close_delay)
+                }))
+                .with_child(
+                    Stepper::new()
+                        .with_range(0.0, 2000.0)
+                        .with_step(100.0)
+                        .lens(AppState::close_delay.map(|x| *x as f64, |x, y| *x = y as u64)),
+                ),
+        )
+        .with_child(
+            Checkbox::new("Move playing and recording streams to a new default")
+                .lens(AppState::move_streams_on_switch)
+                .padding(5.0),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(Label::new(|data: &AppState, _: &Env| {
+                    format!("Volume keys change by {}%", data.volume_step)
+                }))
+                .with_child(
+                    Stepper::new()
+                        .with_range(1.0, 25.0)
+                        .with_step(1.0)
+                        .lens(AppState::volume_step.map(|x| *x as f64, |x, y| *x = y as u32)),
+                ),
+        )
+        .with_child(Label::new("Notify when the default changes because:"))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Checkbox::new("Picked here")
+                        .lens(AppState::notify_on_select)
+                        .padding(5.0),
+                )
+                .with_child(
+                    Checkbox::new("Preferred or app rule")
+                        .lens(AppState::notify_on_automatic)
+                        .padding(5.0),
+                )
+                .with_child(
+                    Checkbox::new("Disconnected")
+                        .lens(AppState::notify_on_fallback)
+                        .padding(5.0),
+                )
+                .with_child(
+                    Checkbox::new("Locked")
+                        .lens(AppState::notify_on_lock)
+                        .padding(5.0),
+                ),
+        )
+        .padding(5.0)
+}
+
+// Lists edited one entry per line.
+fn build_lines(title: &str, lens: impl Lens<AppState, String> + 'static) -> impl Widget<AppState> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(title))
+        .with_child(
+            TextBox::multiline()
+                .with_placeholder("One name, label or glob per line")
+                .lens(lens)
+                .expand_width()
+                .fix_height(80.0)
+                .padding(5.0),
+        )
+}
+
+fn build_rules_tab() -> impl Widget<AppState> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(build_lines(
+            "Switch to these outputs when they connect:",
+            AppState::preferred_sinks,
+        ))
+        .with_child(build_lines(
+            "Switch to these inputs when they connect:",
+            AppState::preferred_sources,
+        ))
+        .with_child(build_lines(
+            "When the output disconnects, use:",
+            AppState::fallback_sinks,
+        ))
+        .with_child(build_lines(
+            "When the input disconnects, use:",
+            AppState::fallback_sources,
+        ))
+        .with_child(
+            Checkbox::new("Hide all monitor sources")
+                .lens(AppState::hide_monitors)
+                .padding(5.0)
+                .controller(RulesController),
+        )
+        .with_child(build_lines("Hide devices named:", AppState::hidden_names))
+        .with_child(build_lines(
+            "Always show devices named:",
+            AppState::shown_names,
+        ))
+        .padding(5.0)
+}
+
+fn build_device_config() -> impl Widget<AudioDeviceState> {
+    let details = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Flex::row()
+                .with_child(Button::new("Inspect").on_click(
+                    |ctx, data: &mut AudioDeviceState, _env| {
+                        ctx.submit_command(
+                            INSPECT_DEVICE.with((data.device_type.clone(), data.name.clone())),
+                        );
+                    },
+                ))
+                .with_child(
+                    Label::new(|data: &String, _env: &_| format!("{}:", data))
+                        .lens(AudioDeviceState::name)
+                        .padding(5.0),
+                ),
+        )
+        .with_child(
+            TextBox::new()
+                .with_placeholder("Automatic label")
                 .lens(AudioDeviceState::label)
                 .padding(5.0)
                 .fix_width(590.0),
         )
         .with_child(
-            Checkbox::new("Hide")
-                .lens(AudioDeviceState::hidden)
-                .padding(5.0),
+            TextBox::new()
+                .with_placeholder("Automatic icon, or an icon name or image path")
+                .lens(AudioDeviceState::icon)
+                .padding(5.0)
+                .fix_width(590.0),
+        )
+        .with_child(Either::new(
+            |data: &AudioDeviceState, _env| data.label.is_empty(),
+            Label::new(|data: &AudioDeviceState, _env: &_| {
+                format!("Automatic: {}", data.default_label)
+            })
+            .padding(5.0),
+            SizedBox::empty(),
+        ))
+        // Forgetting the identity lets the entry learn it again from the
+        // device with its name, when one matched the wrong device.
+        .with_child(Either::new(
+            |data: &AudioDeviceState, _env| data.identity.is_empty(),
+            Label::new("Identity: none").padding(5.0),
+            Flex::row()
+                .with_child(
+                    Label::new(|data: &AudioDeviceState, _env: &_| {
+                        format!("Identity: {}", data.identity.describe())
+                    })
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .padding(5.0)
+                    .fix_width(500.0),
+                )
+                .with_child(Button::new("Forget").on_click(
+                    |_ctx, data: &mut AudioDeviceState, _env| {
+                        data.identity = DeviceIdentity::default();
+                    },
+                )),
+        ))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Checkbox::new("Hide")
+                        .lens(AudioDeviceState::hidden)
+                        .padding(5.0),
+                )
+                .with_child(
+                    Checkbox::new("Favourite")
+                        .lens(AudioDeviceState::favourite)
+                        .padding(5.0),
+                )
+                .with_child(
+                    Checkbox::new("Headphones")
+                        .lens(AudioDeviceState::headphones)
+                        .padding(5.0),
+                )
+                .with_child(Either::new(
+                    |data: &AudioDeviceState, _env| data.partner.is_empty(),
+                    SizedBox::empty(),
+                    Checkbox::new("Switch with its pair")
+                        .lens(AudioDeviceState::unpaired.not())
+                        .padding(5.0),
+                ))
+                .with_child(Either::new(
+                    |data: &AudioDeviceState, _env| data.rule_hidden,
+                    Label::new("Hidden by rule").padding(5.0),
+                    SizedBox::empty(),
+                )),
         )
         .padding((0.0, 5.0))
-        .disabled_if(|data, _env| !data.connected)
+        .disabled_if(|data, _env| !data.connected);
+    // The handle stays enabled so disconnected devices can be reordered too.
+    Flex::row()
+        .with_child(Label::new("☰").center().fix_width(DRAG_HANDLE_WIDTH))
+        .with_child(details)
+        .controller(DragToReorder::default())
+}
+
+// Handles the keys from the key bindings. The screen takes focus when the
+// window opens so that keys reach it, and typing there goes to the search.
+struct KeyboardController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for KeyboardController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        match event {
+            Event::WindowConnected => ctx.request_focus(),
+            Event::KeyDown(key) if handle_key(ctx, key, data) => {
+                ctx.set_handled();
+                return;
+            }
+            _ => (),
+        }
+        let search = data.search.clone();
+        child.event(ctx, event, data, env);
+        if data.search != search {
+            data.search_changed();
+        }
+    }
+}
+
+// Returns whether the key was used.
+fn handle_key(ctx: &mut EventCtx, key: &KeyEvent, data: &mut AppState) -> bool {
+    if data.is_renaming() {
+        match key.key {
+            KbKey::Enter => data.finish_rename(),
+            KbKey::Escape => data.cancel_rename(),
+            _ => return false,
+        }
+        ctx.request_focus();
+        return true;
+    }
+    if let KbKey::Character(text) = &key.key {
+        if !ctx.is_focused() {
+            // The search box has focus and takes the text
+            return false;
+        }
+        let bound = digit(&key.code).is_some() || data.key_bindings().action_for(text).is_some();
+        let shortcut = key.mods.ctrl() || key.mods.alt() || key.mods.meta();
+        if !shortcut && (!data.search.is_empty() || !bound) {
+            data.search.push_str(text);
+            data.search_changed();
+            return true;
+        }
+    }
+    if key.key == KbKey::Backspace && ctx.is_focused() && !data.search.is_empty() {
+        data.search.pop();
+        data.search_changed();
+        return true;
+    }
+    if let Some(n) = digit(&key.code) {
+        let device_type = if key.mods.shift() {
+            AudioDeviceType::Source
+        } else {
+            AudioDeviceType::Sink
+        };
+        data.status = data.select_nth(&device_type, n).join("\n");
+        return true;
+    }
+    let failures = match data.key_bindings().action_for(&key.key.to_string()) {
+        Some(KeyAction::Next) => {
+            data.move_focus(1);
+            Vec::new()
+        }
+        Some(KeyAction::Previous) => {
+            data.move_focus(-1);
+            Vec::new()
+        }
+        Some(KeyAction::NextSection) => {
+            data.focus_next_section();
+            Vec::new()
+        }
+        Some(KeyAction::Select) => data.select_focused(),
+        Some(KeyAction::Mute) => data.toggle_mute_focused(),
+        Some(KeyAction::VolumeUp) => data.change_volume_focused(1),
+        Some(KeyAction::VolumeDown) => data.change_volume_focused(-1),
+        // The first Escape clears the search
+        Some(KeyAction::Close) if !data.search.is_empty() => {
+            data.search.clear();
+            data.search_changed();
+            ctx.request_focus();
+            Vec::new()
+        }
+        Some(KeyAction::Close) => {
+            ctx.submit_command(CLOSE_POPUP);
+            Vec::new()
+        }
+        None => return false,
+    };
+    data.status = failures.join("\n");
+    true
+}
+
+fn digit(code: &Code) -> Option<usize> {
+    let digits = [
+        Code::Digit1,
+        Code::Digit2,
+        Code::Digit3,
+        Code::Digit4,
+        Code::Digit5,
+        Code::Digit6,
+        Code::Digit7,
+        Code::Digit8,
+        Code::Digit9,
+    ];
+    digits.iter().position(|x| x == code).map(|i| i + 1)
+}
+
+// Keeps the device highlighted by the keyboard in view.
+struct ScrollToFocus;
+
+impl<W: Widget<(String, AudioDeviceState)>> Controller<(String, AudioDeviceState), W>
+    for ScrollToFocus
+{
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &(String, AudioDeviceState),
+        data: &(String, AudioDeviceState),
+        env: &Env,
+    ) {
+        if data.1.focused && !old_data.1.focused {
+            ctx.scroll_to_view();
+        }
+        child.update(ctx, old_data, data, env)
+    }
+}
+
+struct DevicesController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for DevicesController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        if let Event::Command(cmd) = event {
+            if let Some((device_type, name)) = cmd.get(SELECT_DEVICE) {
+                data.status = data.select_device(device_type, name).join("\n");
+                ctx.set_handled();
+                return;
+            }
+            if let Some(name) = cmd.get(APPLY_SCENE) {
+                data.status = data.apply_scene(name).join("\n");
+                ctx.set_handled();
+                return;
+            }
+            if let Some(key) = cmd.get(TOGGLE_GROUP) {
+                data.toggle_group(key);
+                ctx.set_handled();
+                return;
+            }
+            if let Some((device, position)) = cmd.get(SHOW_DEVICE_MENU) {
+                ctx.show_context_menu(build_device_menu(data, device), *position);
+                ctx.set_handled();
+                return;
+            }
+            if let Some((device_type, name)) = cmd.get(INSPECT_DEVICE) {
+                show_inspector(ctx, data, env, device_type, name);
+                ctx.set_handled();
+                return;
+            }
+            if cmd.is(FINISH_RENAME) {
+                data.finish_rename();
+                // Keys go nowhere after clicking outside the text box
+                if !ctx.has_focus() {
+                    ctx.request_focus();
+                }
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+// Opens the device menu on right click and moves the keyboard focus to the
+// label when renaming starts.
+struct DeviceMenu {
+    rename_id: WidgetId,
+}
+
+impl<W: Widget<(String, AudioDeviceState)>> Controller<(String, AudioDeviceState), W>
+    for DeviceMenu
+{
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut (String, AudioDeviceState),
+        env: &Env,
+    ) {
+        if let Event::MouseDown(mouse) = event {
+            if mouse.button.is_right() {
+                ctx.submit_command(SHOW_DEVICE_MENU.with((data.1.clone(), mouse.window_pos)));
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env)
+    }
+
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &(String, AudioDeviceState),
+        data: &(String, AudioDeviceState),
+        env: &Env,
+    ) {
+        if data.1.renaming && !old_data.1.renaming {
+            ctx.submit_command(FOCUS_RENAME.to(self.rename_id));
+        }
+        child.update(ctx, old_data, data, env)
+    }
+}
+
+// Takes the focus when asked to and finishes the rename when it loses it.
+struct RenameBox;
+
+impl<W: Widget<(String, AudioDeviceState)>> Controller<(String, AudioDeviceState), W>
+    for RenameBox
+{
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut (String, AudioDeviceState),
+        env: &Env,
+    ) {
+        if let Event::Command(cmd) = event {
+            if cmd.is(FOCUS_RENAME) {
+                ctx.request_focus();
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env)
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &(String, AudioDeviceState),
+        env: &Env,
+    ) {
+        if let LifeCycle::FocusChanged(false) = event {
+            ctx.submit_command(FINISH_RENAME);
+        }
+        child.lifecycle(ctx, event, data, env)
+    }
+}
+
+struct ConfigMenuController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for ConfigMenuController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        if let Event::Command(cmd) = event {
+            if let Some((device_type, name, offset)) = cmd.get(MOVE_DEVICE) {
+                data.move_device(device_type, name, *offset);
+                data.unsaved = true;
+                ctx.set_handled();
+                return;
+            }
+            if let Some((device_type, name)) = cmd.get(INSPECT_DEVICE) {
+                show_inspector(ctx, data, env, device_type, name);
+                ctx.set_handled();
+                return;
+            }
+        }
+        let before = data.clone();
+        child.event(ctx, event, data, env);
+        // Only input counts, as devices coming and going change the data too
+        let input = matches!(
+            event,
+            Event::MouseDown(_)
+                | Event::MouseUp(_)
+                | Event::KeyDown(_)
+                | Event::Paste(_)
+                | Event::ImeStateChange
+        );
+        if input && !before.same(data) {
+            data.unsaved = true;
+        }
+    }
+}
+
+// Dragging a settings row by its handle moves it by as many places as the
+// number of row heights it was dragged over.
+#[derive(Default)]
+struct DragToReorder {
+    start_y: Option<f64>,
+}
+
+impl<W: Widget<AudioDeviceState>> Controller<AudioDeviceState, W> for DragToReorder {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AudioDeviceState,
+        env: &Env,
+    ) {
+        match event {
+            Event::MouseDown(mouse) if mouse.pos.x < DRAG_HANDLE_WIDTH => {
+                self.start_y = Some(mouse.window_pos.y);
+                ctx.set_active(true);
+                ctx.set_handled();
+                return;
+            }
+            Event::MouseMove(mouse) if ctx.is_active() || mouse.pos.x < DRAG_HANDLE_WIDTH => {
+                ctx.set_cursor(&Cursor::ResizeUpDown);
+            }
+            Event::MouseMove(_) => ctx.clear_cursor(),
+            Event::MouseUp(mouse) if ctx.is_active() => {
+                ctx.set_active(false);
+                if let Some(start_y) = self.start_y.take() {
+                    let offset = ((mouse.window_pos.y - start_y) / ctx.size().height).round();
+                    if offset != 0.0 {
+                        ctx.submit_command(MOVE_DEVICE.with((
+                            data.device_type.clone(),
+                            data.name.clone(),
+                            offset as isize,
+                        )));
+                    }
+                }
+                ctx.set_handled();
+                return;
+            }
+            _ => {}
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+// Re-evaluates hide rules whenever the settings that feed them change.
+struct RulesController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for RulesController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        let hide_monitors = data.hide_monitors;
+        child.event(ctx, event, data, env);
+        if data.hide_monitors != hide_monitors {
+            data.apply_rules();
+        }
+    }
 }
 
-fn get_shortened_label(label: &String) -> String {
+fn get_shortened_label(label: &str) -> String {
     if label.is_ascii() {
         let len = label.len();
         if len > 35 {
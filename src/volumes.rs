@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const STATE_NAME: &str = "state";
+
+#[derive(Serialize, Clone, Copy, Deserialize, Debug, PartialEq)]
+pub struct VolumeState {
+    pub volume: u32, // percent
+    pub mute: bool,
+}
+
+// The last volume and mute state seen for each device by name, kept in
+// state.toml next to the config since it changes all the time.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SavedVolumes {
+    devices: BTreeMap<String, VolumeState>,
+    // Whether each source was muted before all inputs were muted, present
+    // while they are
+    inputs_before_mute: Option<BTreeMap<String, bool>>,
+}
+
+impl SavedVolumes {
+    pub fn load() -> Self {
+        confy::load("audio-select", Some(STATE_NAME)).unwrap_or_else(|e| {
+            eprintln!("Failed to load saved volumes: {}", e);
+            SavedVolumes::default()
+        })
+    }
+
+    fn store(&self) {
+        if let Err(e) = confy::store("audio-select", Some(STATE_NAME), self) {
+            eprintln!("Failed to store saved volumes: {}", e);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<VolumeState> {
+        self.devices.get(name).copied()
+    }
+
+    // Stores the states that changed since they were last saved.
+    pub fn remember(&mut self, states: impl Iterator<Item = (String, VolumeState)>) {
+        let mut changed = false;
+        for (name, state) in states {
+            if self.devices.get(&name) != Some(&state) {
+                self.devices.insert(name, state);
+                changed = true;
+            }
+        }
+        if changed {
+            self.store();
+        }
+    }
+
+    pub fn inputs_before_mute(&self) -> Option<&BTreeMap<String, bool>> {
+        self.inputs_before_mute.as_ref()
+    }
+
+    pub fn set_inputs_before_mute(&mut self, inputs: Option<BTreeMap<String, bool>>) {
+        self.inputs_before_mute = inputs;
+        self.store();
+    }
+}
@@ -0,0 +1,43 @@
+use crate::data::AppState;
+use crate::pulsewrapper::{PulseWrapper, PulseWrapperError, ServerEvent};
+use druid::ExtEventSink;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+// Opens a separate connection and passes every batch of server events to
+// on_events. Only returns when the connection fails or is lost.
+pub fn run(mut on_events: impl FnMut(Vec<ServerEvent>)) -> PulseWrapperError {
+    let mut pulsewrapper = PulseWrapper::new();
+    if let PulseWrapperError::Err = pulsewrapper.connect() {
+        return PulseWrapperError::Err;
+    }
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let events2 = Rc::clone(&events);
+    if let PulseWrapperError::Err = pulsewrapper.subscribe(move |x| events2.borrow_mut().push(x)) {
+        return PulseWrapperError::Err;
+    }
+    loop {
+        if let PulseWrapperError::Err = pulsewrapper.wait_for_events() {
+            return PulseWrapperError::Err;
+        }
+        let batch = events.take();
+        if !batch.is_empty() {
+            on_events(batch);
+        }
+    }
+}
+
+// Watches from a background thread for as long as the popup is open,
+// reconnecting after pulseaudio restarts.
+pub fn spawn(sink: ExtEventSink) {
+    thread::spawn(move || loop {
+        run(|events| {
+            sink.add_idle_callback(move |data: &mut AppState| data.handle_events(&events));
+        });
+        thread::sleep(RECONNECT_DELAY);
+    });
+}